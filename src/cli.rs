@@ -1,9 +1,9 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
 use serde::Deserialize;
 
 /// What to do with the selected container.
-#[derive(Subcommand, Deserialize, Debug, Clone, Copy)]
+#[derive(Subcommand, Deserialize, Debug, Clone)]
 pub enum Command {
     /// Focus the container
     #[command(about = "Focus the selected window (default)")]
@@ -15,11 +15,284 @@ pub enum Command {
         /// Also focus the selected window after swapping
         #[arg(long)]
         focus: bool,
+
+        /// Pick both windows to swap: the first hint selects the source, the second the destination
+        #[arg(long)]
+        pick_two: bool,
+    },
+
+    /// Move the container to the current workspace and focus it
+    #[command(about = "Move the selected window to the current workspace and focus it")]
+    Bring,
+
+    /// Close the container
+    #[command(about = "Close the selected window")]
+    Kill,
+
+    /// Toggle floating on the container and focus it
+    #[command(about = "Toggle floating on the selected window and focus it")]
+    Float,
+
+    /// Toggle fullscreen on the container and focus it
+    #[command(about = "Toggle fullscreen on the selected window and focus it")]
+    Fullscreen,
+
+    /// Toggle sticky on the container
+    #[command(about = "Toggle sticky on the selected (floating) window")]
+    Sticky,
+
+    /// Move the container to the scratchpad
+    #[command(about = "Move the selected window to the scratchpad")]
+    Scratchpad,
+
+    /// Move the cursor to the center of the container
+    #[command(about = "Move the mouse cursor to the center of the selected window")]
+    Cursor,
+
+    /// Resize the container with the resize keys until Enter or Escape
+    #[command(about = "Resize the selected window with h/j/k/l until Enter or Escape")]
+    Resize,
+
+    /// Float the container and fit it to a cell of a grid over its output
+    #[command(about = "Float the selected window and place it on a grid of hints")]
+    Place,
+
+    /// Narrow down a grid over the focused output and move the cursor there
+    #[command(about = "Move the mouse cursor by narrowing down a grid of hints")]
+    Pointer {
+        /// Click once the cursor has moved
+        #[arg(long)]
+        click: bool,
+
+        /// The button to click, e.g. button1 (left), button2 (middle) or button3 (right)
+        #[arg(long, default_value = "button1")]
+        button: String,
+    },
+
+    /// Add a mark to the container, named by the key typed after the hint
+    #[command(about = "Mark the selected window with the next letter typed")]
+    Mark,
+
+    /// Focus a marked container by typing its mark
+    #[command(name = "goto-mark", about = "Focus the window with the typed mark")]
+    GotoMark,
+
+    /// Print the container's ID or a description of it
+    #[command(about = "Print the selected window's ID or details")]
+    Print {
+        /// How to describe the selected window
+        #[arg(long, value_enum, default_value_t = PrintFormat::Id)]
+        format: PrintFormat,
+
+        /// Template for `--format template`, e.g. '{app_id}: {title}'
+        #[arg(long, required_if_eq("format", "template"))]
+        template: Option<String>,
+    },
+
+    /// Copy the container's ID or a description of it to the clipboard
+    #[command(about = "Copy the selected window's ID or details to the clipboard")]
+    Yank {
+        /// How to describe the selected window
+        #[arg(long, value_enum, default_value_t = PrintFormat::Id)]
+        format: PrintFormat,
+
+        /// Template for `--format template`, e.g. '{app_id}: {title}'
+        #[arg(long, required_if_eq("format", "template"))]
+        template: Option<String>,
+    },
+
+    /// Run a command with the container's details in its environment
+    #[command(about = "Run a command with the selected window's details in its environment")]
+    Exec {
+        /// The command to start after the overlay closes, a shell command if it is one argument
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        command: Vec<String>,
     },
 
-    /// Print the container's ID
-    #[command(about = "Print the selected window's ID")]
+    /// Focus the previously focused window, as tracked by the daemon
+    #[command(about = "Focus the previously focused window, as tracked by the daemon")]
+    Previous,
+
+    /// Keep running in the background so `trigger` can show the overlay instantly
+    #[command(
+        about = "Keep running in the background so `trigger` can show the overlay instantly"
+    )]
+    Daemon,
+
+    /// Show the running daemon's overlay
+    #[command(about = "Show the running daemon's overlay, with options applied on top of its own")]
+    Trigger {
+        /// What the daemon should do with the selection, e.g. `kill`, `focus` if not given
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+}
+
+/// Commands that can be bound to a modifier, see `--shift-action` and friends.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Focus the selected window
+    Focus,
+    /// Swap the focused window with the selected window
+    Swap,
+    /// Move the selected window to the current workspace and focus it
+    Bring,
+    /// Close the selected window
+    Kill,
+    /// Toggle floating on the selected window and focus it
+    Float,
+    /// Toggle fullscreen on the selected window and focus it
+    Fullscreen,
+    /// Toggle sticky on the selected window
+    Sticky,
+    /// Move the selected window to the scratchpad
+    Scratchpad,
+    /// Mark the selected window with the next letter typed
+    Mark,
+    /// Move the mouse cursor to the center of the selected window
+    Cursor,
+    /// Resize the selected window with the resize keys
+    Resize,
+    /// Float the selected window and place it on a grid of hints
+    Place,
+    /// Print the selected window's ID
     Print,
+    /// Copy the selected window's ID to the clipboard
+    Yank,
+}
+
+impl Action {
+    /// The key that picks this action in the action menu.
+    pub fn menu_key(self) -> char {
+        match self {
+            Action::Focus => 'f',
+            Action::Swap => 's',
+            Action::Bring => 'b',
+            Action::Kill => 'k',
+            Action::Float => 'l',
+            Action::Fullscreen => 'u',
+            Action::Sticky => 't',
+            Action::Scratchpad => 'c',
+            Action::Mark => 'm',
+            Action::Cursor => 'r',
+            Action::Resize => 'z',
+            Action::Place => 'g',
+            Action::Print => 'p',
+            Action::Yank => 'y',
+        }
+    }
+
+    /// The name shown for this action in the action menu.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Focus => "focus",
+            Action::Swap => "swap",
+            Action::Bring => "bring",
+            Action::Kill => "kill",
+            Action::Float => "float",
+            Action::Fullscreen => "fullscreen",
+            Action::Sticky => "sticky",
+            Action::Scratchpad => "scratchpad",
+            Action::Mark => "mark",
+            Action::Cursor => "cursor",
+            Action::Resize => "resize",
+            Action::Place => "place",
+            Action::Print => "print",
+            Action::Yank => "yank",
+        }
+    }
+}
+
+impl From<Action> for Command {
+    fn from(action: Action) -> Self {
+        match action {
+            Action::Focus => Command::Focus,
+            Action::Swap => Command::Swap {
+                focus: false,
+                pick_two: false,
+            },
+            Action::Bring => Command::Bring,
+            Action::Kill => Command::Kill,
+            Action::Float => Command::Float,
+            Action::Fullscreen => Command::Fullscreen,
+            Action::Sticky => Command::Sticky,
+            Action::Scratchpad => Command::Scratchpad,
+            Action::Mark => Command::Mark,
+            Action::Cursor => Command::Cursor,
+            Action::Resize => Command::Resize,
+            Action::Place => Command::Place,
+            Action::Print => Command::Print {
+                format: PrintFormat::Id,
+                template: None,
+            },
+            Action::Yank => Command::Yank {
+                format: PrintFormat::Id,
+                template: None,
+            },
+        }
+    }
+}
+
+/// Which container the focus command focuses, relative to the selected window.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum FocusTarget {
+    /// The selected window itself
+    Leaf,
+    /// The container holding the selected window
+    Parent,
+    /// The outermost container holding the selected window, i.e. the workspace's child
+    #[value(alias = "child")]
+    #[serde(alias = "child")]
+    Outer,
+}
+
+/// Compositors that can be driven, see `--backend`.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Whichever compositor the environment points at
+    Auto,
+    Sway,
+    Hyprland,
+    /// i3 on X11
+    I3,
+    Niri,
+    /// Any compositor with wlroots' foreign toplevel management, listing windows by name
+    Wlroots,
+}
+
+/// How hints are handed out to the visible windows.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum HintOrder {
+    /// By where the windows are, output by output
+    Position,
+    /// Most recently used windows first, as tracked by the daemon
+    Mru,
+}
+
+/// Output formats for the print command.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum PrintFormat {
+    /// The container's ID
+    Id,
+    /// The window's title
+    Title,
+    /// The window's app_id
+    #[value(name = "app_id")]
+    #[serde(rename = "app_id")]
+    AppId,
+    /// A JSON object describing the window
+    Json,
+    /// Tab-separated fields: id, app_id, class, title, pid, workspace, output, rect, floating, marks
+    Tsv,
+    /// The window's geometry as `x,y wxh`, e.g. for `grim -g`
+    Slurp,
+    /// A custom template with `{field}` placeholders
+    Template,
 }
 
 /// A tool to help efficiently focus windows in Sway inspired by i3-easyfocus.
@@ -62,6 +335,22 @@ pub struct Args {
     #[arg(long)]
     pub focused_text_color: Option<String>,
 
+    /// set the selected label background color in multi-select mode <rrggbb>
+    #[arg(long)]
+    pub selected_background_color: Option<String>,
+
+    /// set the selected background opacity <0-1.0>
+    #[arg(long)]
+    pub selected_background_opacity: Option<f64>,
+
+    /// set the selected text color <rrggbb>
+    #[arg(long)]
+    pub selected_text_color: Option<String>,
+
+    /// set the color of marks shown next to hints <rrggbb>
+    #[arg(long)]
+    pub mark_text_color: Option<String>,
+
     /// set the font family
     #[arg(long)]
     pub font_family: Option<String>,
@@ -94,6 +383,78 @@ pub struct Args {
     #[arg(long = "show-confirmation")]
     pub show_confirmation: Option<bool>,
 
+    /// Close the overlay if no key is pressed for this long, 0 to wait forever <ms>
+    #[arg(long)]
+    pub timeout_ms: Option<u64>,
+
+    /// Close the overlay when it is clicked or the focused workspace changes
+    #[arg(long)]
+    pub cancel_on_focus_loss: Option<bool>,
+
+    /// Show each window's sway marks next to its hint
+    #[arg(long)]
+    pub show_marks: Option<bool>,
+
+    /// Allow typing ' and a mark's name to select its window
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub select_by_mark: Option<bool>,
+
+    /// Focus the selected window, its parent container, or its outermost container
+    #[arg(long, value_enum)]
+    pub focus_target: Option<FocusTarget>,
+
+    /// Move the mouse cursor to the center of the selected window afterwards
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub warp_cursor: Option<bool>,
+
+    /// The seat whose cursor is warped ('-' for the current seat)
+    #[arg(long)]
+    pub seat: Option<String>,
+
+    /// keys that shrink width, grow height, shrink height and grow width in resize mode <hjkl>
+    #[arg(long)]
+    pub resize_keys: Option<String>,
+
+    /// set how much each resize key resizes by <px>
+    #[arg(long)]
+    pub resize_step: Option<i32>,
+
+    /// set the number of rows in hint grids
+    #[arg(long)]
+    pub grid_rows: Option<i32>,
+
+    /// set the number of columns in hint grids
+    #[arg(long)]
+    pub grid_columns: Option<i32>,
+
+    /// The compositor to talk to, found from the environment by default
+    #[arg(long, value_enum)]
+    pub backend: Option<Backend>,
+
+    /// Hand out the first hint characters by position or to the most recently used windows
+    #[arg(long, value_enum)]
+    pub hint_order: Option<HintOrder>,
+
+    /// Toggle several windows with their hints and apply the command to all of them on Enter
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub multi: Option<bool>,
+
+    /// Show a menu of actions next to the selected window instead of running the command
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub menu: Option<bool>,
+
+    /// Command to run when a hint is typed with Shift held
+    #[arg(long, value_enum)]
+    pub shift_action: Option<Action>,
+
+    /// Command to run when a hint is typed with Ctrl held
+    #[arg(long, value_enum)]
+    pub ctrl_action: Option<Action>,
+
+    /// Command to run when a hint is typed with Alt held
+    #[arg(long, value_enum)]
+    pub alt_action: Option<Action>,
+
     /// The selected command
     #[command(subcommand)]
     #[serde(skip)]
@@ -130,6 +491,18 @@ impl Args {
         if other.focused_text_color.is_some() {
             self.focused_text_color = other.focused_text_color.clone();
         }
+        if other.selected_background_color.is_some() {
+            self.selected_background_color = other.selected_background_color.clone();
+        }
+        if other.selected_background_opacity.is_some() {
+            self.selected_background_opacity = other.selected_background_opacity;
+        }
+        if other.selected_text_color.is_some() {
+            self.selected_text_color = other.selected_text_color.clone();
+        }
+        if other.mark_text_color.is_some() {
+            self.mark_text_color = other.mark_text_color.clone();
+        }
         if other.font_family.is_some() {
             self.font_family = other.font_family.clone();
         }
@@ -152,13 +525,80 @@ impl Args {
             self.label_margin_y = other.label_margin_y;
         }
         if other.command.is_some() {
-            self.command = other.command;
+            self.command = other.command.clone();
         }
 
         // Handle confirmation flag
         if other.show_confirmation.is_some() {
             self.show_confirmation = other.show_confirmation;
         }
+        if other.timeout_ms.is_some() {
+            self.timeout_ms = other.timeout_ms;
+        }
+        if other.cancel_on_focus_loss.is_some() {
+            self.cancel_on_focus_loss = other.cancel_on_focus_loss;
+        }
+        if other.show_marks.is_some() {
+            self.show_marks = other.show_marks;
+        }
+        if other.select_by_mark.is_some() {
+            self.select_by_mark = other.select_by_mark;
+        }
+        if other.focus_target.is_some() {
+            self.focus_target = other.focus_target;
+        }
+        if other.warp_cursor.is_some() {
+            self.warp_cursor = other.warp_cursor;
+        }
+        if other.seat.is_some() {
+            self.seat = other.seat.clone();
+        }
+        if other.resize_keys.is_some() {
+            self.resize_keys = other.resize_keys.clone();
+        }
+        if other.resize_step.is_some() {
+            self.resize_step = other.resize_step;
+        }
+        if other.grid_rows.is_some() {
+            self.grid_rows = other.grid_rows;
+        }
+        if other.grid_columns.is_some() {
+            self.grid_columns = other.grid_columns;
+        }
+        if other.backend.is_some() {
+            self.backend = other.backend;
+        }
+        if other.hint_order.is_some() {
+            self.hint_order = other.hint_order;
+        }
+        if other.multi.is_some() {
+            self.multi = other.multi;
+        }
+        if other.menu.is_some() {
+            self.menu = other.menu;
+        }
+        if other.shift_action.is_some() {
+            self.shift_action = other.shift_action;
+        }
+        if other.ctrl_action.is_some() {
+            self.ctrl_action = other.ctrl_action;
+        }
+        if other.alt_action.is_some() {
+            self.alt_action = other.alt_action;
+        }
+    }
+
+    /// Checks the options that can't be checked while parsing, since the config file may set them.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("grid_rows", self.grid_rows),
+            ("grid_columns", self.grid_columns),
+        ] {
+            if value.is_some_and(|value| value < 1) {
+                return Err(format!("{} must be at least 1", name));
+            }
+        }
+        Ok(())
     }
 }
 
@@ -174,6 +614,10 @@ impl Default for Args {
             focused_background_color: Some("285577".to_string()),
             focused_background_opacity: Some(1.0),
             focused_text_color: Some("ffffff".to_string()),
+            selected_background_color: Some("b5bd68".to_string()),
+            selected_background_opacity: Some(1.0),
+            selected_text_color: Some("1d1f21".to_string()),
+            mark_text_color: Some("81a2be".to_string()),
             font_family: Some("monospace".to_string()),
             font_weight: Some("bold".to_string()),
             font_size: Some("medium".to_string()),
@@ -182,6 +626,25 @@ impl Default for Args {
             label_margin_x: Some(4),
             label_margin_y: Some(2),
             show_confirmation: Some(true),
+            timeout_ms: Some(0),
+            cancel_on_focus_loss: Some(true),
+            show_marks: Some(true),
+            select_by_mark: Some(false),
+            focus_target: Some(FocusTarget::Leaf),
+            warp_cursor: Some(false),
+            seat: Some("-".to_string()),
+            resize_keys: Some("hjkl".to_string()),
+            resize_step: Some(20),
+            grid_rows: Some(3),
+            grid_columns: Some(3),
+            backend: Some(Backend::Auto),
+            hint_order: Some(HintOrder::Position),
+            multi: Some(false),
+            menu: Some(false),
+            // modifiers are often still held from the binding that opened the overlay
+            shift_action: None,
+            ctrl_action: None,
+            alt_action: None,
             command: Some(Command::Focus),
         }
     }
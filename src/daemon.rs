@@ -0,0 +1,465 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    sync::{mpsc, Arc, Mutex},
+    time::Duration,
+};
+
+use clap::Parser;
+use gtk4::{gio, glib, prelude::*, Application};
+use serde_json::{json, Value};
+
+use crate::{
+    cli::{Args, Command, PrintFormat},
+    ui, utils,
+    wm::{Event, Layout, SharedWindowManager},
+};
+
+/// How long a client gets to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn socket_path() -> PathBuf {
+    xdg::BaseDirectories::with_prefix("sway-easyfocus")
+        .place_runtime_file("daemon.sock")
+        .expect("failed to create runtime directory")
+}
+
+/// Keeps GTK and a copy of the layout around, showing the overlay whenever a client asks.
+pub fn run_daemon(wm: SharedWindowManager, args: Arc<Args>) {
+    // don't let another instance's D-Bus name stand in for this one
+    let app = Application::builder()
+        .application_id("com.github.edzdez.sway-easyfocus")
+        .flags(gio::ApplicationFlags::NON_UNIQUE)
+        .build();
+
+    let args_clone = args.clone();
+    app.connect_startup(move |app| {
+        ui::load_css(args_clone.clone());
+        // keep running without any windows open
+        std::mem::forget(app.hold());
+    });
+
+    app.connect_activate(move |_| {
+        let path = socket_path();
+        // a previous daemon may have left its socket behind
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).expect("failed to bind daemon socket");
+
+        let cache = LayoutCache::spawn(&wm);
+        let wm = wm.clone();
+        let args = args.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let wm = wm.clone();
+                let args = args.clone();
+                let cache = cache.clone();
+                // a client that never finishes its request mustn't hold up the others
+                std::thread::spawn(move || {
+                    let _ = stream.set_read_timeout(Some(REQUEST_TIMEOUT));
+                    let mut request = String::new();
+                    if BufReader::new(&stream).read_line(&mut request).is_err() {
+                        return;
+                    }
+                    let _ = stream.set_read_timeout(None);
+                    let request: Value = serde_json::from_str(&request).unwrap_or_default();
+
+                    glib::MainContext::default().invoke(move || {
+                        let app = gio::Application::default()
+                            .and_then(|app| app.downcast::<Application>().ok())
+                            .expect("failed to get application");
+                        let showing = !app.windows().is_empty();
+                        let overlay_wm = wm.clone();
+                        let overlay_cache = cache.clone();
+                        handle_request(
+                            wm,
+                            &args,
+                            &cache,
+                            request,
+                            stream,
+                            showing,
+                            move |args, on_close| {
+                                ui::show_overlay(
+                                    &app,
+                                    args,
+                                    overlay_wm,
+                                    overlay_cache.get(),
+                                    overlay_cache.subscribe(),
+                                    &overlay_cache.mru(),
+                                    on_close,
+                                )
+                            },
+                        );
+                    });
+                });
+            }
+        });
+    });
+
+    let empty: Vec<String> = vec![];
+    app.run_with_args(&empty);
+}
+
+/// A copy of the layout that is kept up to date in the background, so the daemon can show the
+/// overlay without waiting on the compositor, along with the order windows were last focused in.
+#[derive(Clone)]
+struct LayoutCache {
+    layout: Arc<Mutex<Layout>>,
+    mru: Arc<Mutex<Vec<i64>>>,
+    /// Overlays following the layout, which share the daemon's one subscription.
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+type Subscriber = mpsc::Sender<(Event, Layout)>;
+
+impl LayoutCache {
+    fn spawn(wm: &SharedWindowManager) -> Self {
+        let mut wm = wm.lock().unwrap();
+        let layout = wm.layout();
+        let mru = layout
+            .windows
+            .iter()
+            .filter(|window| window.focused)
+            .map(|window| window.id)
+            .collect();
+        let cache = LayoutCache {
+            layout: Arc::new(Mutex::new(layout)),
+            mru: Arc::new(Mutex::new(mru)),
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+        };
+
+        let cache_clone = cache.clone();
+        let events = wm.subscribe();
+        std::thread::spawn(move || {
+            for (event, layout) in events {
+                let mut mru = cache_clone.mru.lock().unwrap();
+                match event {
+                    Event::Focus(con_id) => {
+                        mru.retain(|&id| id != con_id);
+                        mru.insert(0, con_id);
+                    }
+                    Event::Close(con_id) => mru.retain(|&id| id != con_id),
+                    Event::Change => {}
+                }
+                // closed overlays have dropped their receivers
+                cache_clone
+                    .subscribers
+                    .lock()
+                    .unwrap()
+                    .retain(|sender| sender.send((event, layout.clone())).is_ok());
+                *cache_clone.layout.lock().unwrap() = layout;
+            }
+        });
+
+        cache
+    }
+
+    fn get(&self) -> Layout {
+        self.layout.lock().unwrap().clone()
+    }
+
+    /// Layout changes from now on, until the receiver is dropped.
+    fn subscribe(&self) -> mpsc::Receiver<(Event, Layout)> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// IDs of the windows focused since the daemon started, most recent first.
+    fn mru(&self) -> Vec<i64> {
+        self.mru.lock().unwrap().clone()
+    }
+}
+
+/// Called with what was picked once the overlay closes.
+type OnClose = Box<dyn FnOnce(ui::Selection)>;
+
+/// The options and command a request asks for, on top of the daemon's own.
+fn request_args(base_args: &Args, request: &Value) -> Result<Args, String> {
+    let mut args = base_args.clone();
+    if let Some(argv) = request.get("argv") {
+        let argv: Vec<String> =
+            serde_json::from_value(argv.clone()).map_err(|err| err.to_string())?;
+        let trigger_args = Args::try_parse_from(argv).map_err(|err| err.to_string())?;
+        args.merge(&trigger_args);
+        // run the trigger's inner command
+        args.command = match trigger_args.command {
+            Some(Command::Trigger { command }) if !command.is_empty() => parse_command(command)?,
+            Some(Command::Previous) => Some(Command::Previous),
+            _ => Some(Command::Focus),
+        };
+    } else if let Some(options) = request.get("options") {
+        let options: Args =
+            serde_json::from_value(options.clone()).map_err(|err| err.to_string())?;
+        args.merge(&options);
+        // scripts just want to know what was picked unless they ask for more
+        args.command = match request.get("command") {
+            Some(command) => parse_command(
+                serde_json::from_value(command.clone()).map_err(|err| err.to_string())?,
+            )?,
+            None => Some(Command::Print {
+                format: PrintFormat::Id,
+                template: None,
+            }),
+        };
+    } else {
+        return Err("unknown request".to_string());
+    }
+    args.validate()?;
+    Ok(args)
+}
+
+/// Parses a command given as on the command line, e.g. `["print", "--format", "title"]`.
+fn parse_command(command: Vec<String>) -> Result<Option<Command>, String> {
+    let argv = std::iter::once("sway-easyfocus".to_string()).chain(command);
+    match Args::try_parse_from(argv)
+        .map_err(|err| err.to_string())?
+        .command
+    {
+        // these only make sense from the command line
+        Some(Command::Daemon | Command::Trigger { .. } | Command::Previous) => {
+            Err("that command can't be run by the daemon".to_string())
+        }
+        command => Ok(command),
+    }
+}
+
+/// Answers one request, either `{"argv": [...]}` from `trigger` or `{"options": {...}}` from a
+/// script, with the options named as in the config file and an optional `"command": [...]`
+/// given as on the command line. `show` puts up the overlay unless one is already `showing`.
+fn handle_request(
+    wm: SharedWindowManager,
+    base_args: &Args,
+    cache: &LayoutCache,
+    request: Value,
+    mut stream: UnixStream,
+    showing: bool,
+    show: impl FnOnce(Arc<Args>, OnClose),
+) {
+    let args = match request_args(base_args, &request) {
+        Ok(args) => args,
+        Err(err) => {
+            respond(&mut stream, json!({ "error": err }));
+            return;
+        }
+    };
+
+    if let Some(Command::Previous) = args.command {
+        previous(wm, cache);
+        respond(&mut stream, json!({ "output": [] }));
+        return;
+    }
+    if showing {
+        respond(
+            &mut stream,
+            json!({ "error": "the overlay is already showing" }),
+        );
+        return;
+    }
+    let args = Arc::new(args);
+
+    let args_clone = args.clone();
+    show(
+        args,
+        Box::new(move |selection| {
+            if selection.windows.is_empty() && selection.pointer.is_none() {
+                respond(&mut stream, json!({ "cancelled": true }));
+                return;
+            }
+            let windows: Vec<Value> = selection.windows.iter().map(utils::window_json).collect();
+            let pointer = selection.pointer;
+            let output = ui::finish_selection(wm, &args_clone, selection);
+            respond(
+                &mut stream,
+                json!({ "windows": windows, "pointer": pointer, "output": output }),
+            );
+        }),
+    );
+}
+
+/// Focuses the most recently used window that still exists, other than the focused one.
+fn previous(wm: SharedWindowManager, cache: &LayoutCache) {
+    let layout = cache.get();
+    if let Some(&con_id) = cache
+        .mru()
+        .iter()
+        .find(|&&con_id| layout.window(con_id).is_some_and(|window| !window.focused))
+    {
+        wm.lock().unwrap().focus(con_id);
+    }
+}
+
+fn respond(stream: &mut UnixStream, response: Value) {
+    // the client may have given up waiting already, which is fine
+    let _ = writeln!(stream, "{}", response);
+}
+
+/// Asks the daemon to show the overlay, printing whatever it sends back.
+pub fn trigger() {
+    let mut stream = UnixStream::connect(socket_path())
+        .expect("failed to connect to daemon, is `sway-easyfocus daemon` running?");
+    let argv: Vec<String> = std::env::args().collect();
+    writeln!(stream, "{}", json!({ "argv": argv })).expect("failed to send request to daemon");
+
+    let mut response = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut response)
+        .expect("failed to read response from daemon");
+    let response: Value = serde_json::from_str(&response).expect("invalid response from daemon");
+
+    if let Some(err) = response["error"].as_str() {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    for line in response["output"].as_array().into_iter().flatten() {
+        if let Some(line) = line.as_str() {
+            println!("{}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wm::{mock, Rect};
+
+    /// Sends `request` to `handle_request` and returns its response.
+    fn ask(
+        wm: SharedWindowManager,
+        cache: &LayoutCache,
+        request: Value,
+        showing: bool,
+        show: impl FnOnce(Arc<Args>, OnClose),
+    ) -> Value {
+        let (client, server) = UnixStream::pair().unwrap();
+        handle_request(wm, &Args::default(), cache, request, server, showing, show);
+
+        let mut response = String::new();
+        BufReader::new(client).read_line(&mut response).unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    fn cache(layout: Layout, mru: Vec<i64>) -> LayoutCache {
+        LayoutCache {
+            layout: Arc::new(Mutex::new(layout)),
+            mru: Arc::new(Mutex::new(mru)),
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    fn no_overlay(_: Arc<Args>, _: OnClose) {
+        panic!("the overlay shouldn't be shown");
+    }
+
+    /// Dismisses the overlay straight away.
+    fn dismiss(_: Arc<Args>, on_close: OnClose) {
+        on_close(ui::Selection::default());
+    }
+
+    #[test]
+    fn unknown_requests_are_errors() {
+        let wm = mock::MockWindowManager::new(Layout::default());
+        let response = ask(
+            wm,
+            &cache(Layout::default(), vec![]),
+            json!({}),
+            false,
+            no_overlay,
+        );
+        assert_eq!(response, json!({ "error": "unknown request" }));
+    }
+
+    #[test]
+    fn bad_options_are_errors() {
+        let cache = cache(Layout::default(), vec![]);
+        for request in [
+            json!({ "argv": ["sway-easyfocus", "--no-such-option", "trigger"] }),
+            json!({ "options": { "chars": 3 } }),
+            json!({ "options": {}, "command": ["no-such-command"] }),
+            json!({ "argv": "focus" }),
+            json!({ "options": { "grid_rows": 0 } }),
+            json!({ "options": {}, "command": ["daemon"] }),
+            json!({ "options": {}, "command": ["trigger", "kill"] }),
+            json!({ "argv": ["sway-easyfocus", "trigger", "previous"] }),
+        ] {
+            let wm = mock::MockWindowManager::new(Layout::default());
+            let response = ask(wm, &cache, request, false, no_overlay);
+            assert!(response["error"].is_string(), "{}", response);
+        }
+    }
+
+    #[test]
+    fn only_one_overlay_is_shown() {
+        let wm = mock::MockWindowManager::new(Layout::default());
+        let cache = cache(Layout::default(), vec![]);
+        let response = ask(wm, &cache, json!({ "options": {} }), true, no_overlay);
+        assert_eq!(
+            response,
+            json!({ "error": "the overlay is already showing" })
+        );
+    }
+
+    #[test]
+    fn dismissed_overlays_are_cancelled() {
+        let wm = mock::MockWindowManager::new(Layout::default());
+        let cache = cache(Layout::default(), vec![]);
+        let request = json!({ "argv": ["sway-easyfocus", "trigger", "kill"] });
+        let response = ask(wm.clone(), &cache, request, false, |args, on_close| {
+            assert!(matches!(args.command, Some(Command::Kill)));
+            dismiss(args, on_close);
+        });
+        assert_eq!(response, json!({ "cancelled": true }));
+        assert!(wm.lock().unwrap().calls.is_empty());
+    }
+
+    #[test]
+    fn option_requests_print_unless_given_a_command() {
+        let cache = cache(Layout::default(), vec![]);
+        let wm = mock::MockWindowManager::new(Layout::default());
+        let request = json!({ "options": { "chars": "asdf" } });
+        ask(wm, &cache, request, false, |args, on_close| {
+            assert_eq!(args.chars.as_deref(), Some("asdf"));
+            assert!(matches!(
+                args.command,
+                Some(Command::Print {
+                    format: PrintFormat::Id,
+                    ..
+                })
+            ));
+            dismiss(args, on_close);
+        });
+
+        let wm = mock::MockWindowManager::new(Layout::default());
+        let request = json!({ "options": {}, "command": ["print", "--format", "title"] });
+        ask(wm, &cache, request, false, |args, on_close| {
+            assert!(matches!(
+                args.command,
+                Some(Command::Print {
+                    format: PrintFormat::Title,
+                    ..
+                })
+            ));
+            dismiss(args, on_close);
+        });
+    }
+
+    #[test]
+    fn previous_focuses_the_last_window() {
+        let rect = Rect::default();
+        let mut focused = mock::window(1, rect);
+        focused.focused = true;
+        let layout = mock::layout(vec![focused, mock::window(2, rect)]);
+        let wm = mock::MockWindowManager::new(layout.clone());
+
+        let request = json!({ "argv": ["sway-easyfocus", "previous"] });
+        let response = ask(
+            wm.clone(),
+            &cache(layout, vec![1, 3, 2]),
+            request,
+            false,
+            no_overlay,
+        );
+        assert_eq!(response, json!({ "output": [] }));
+        assert_eq!(wm.lock().unwrap().calls, ["focus 2"]);
+    }
+}
@@ -0,0 +1,102 @@
+use gtk4::prelude::*;
+
+/// A rectangle in output-local coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Region {
+    pub fn center(&self) -> (i32, i32) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    /// Splits the region into `rows` x `columns` cells, row by row.
+    pub fn cells(&self, rows: i32, columns: i32) -> Vec<Region> {
+        let mut cells = vec![];
+        for row in 0..rows {
+            // spread the leftover pixels across the cells instead of piling them into the last one
+            let y0 = self.y + self.height * row / rows;
+            let y1 = self.y + self.height * (row + 1) / rows;
+            for column in 0..columns {
+                let x0 = self.x + self.width * column / columns;
+                let x1 = self.x + self.width * (column + 1) / columns;
+                cells.push(Region {
+                    x: x0,
+                    y: y0,
+                    width: x1 - x0,
+                    height: y1 - y0,
+                });
+            }
+        }
+        cells
+    }
+}
+
+/// Replaces the contents of `fixed` with `cells`, each outlined and labelled with its hint.
+pub fn draw_grid(fixed: &gtk4::Fixed, cells: &[Region], hints: &[char]) {
+    while let Some(child) = fixed.first_child() {
+        fixed.remove(&child);
+    }
+
+    for (cell, hint) in cells.iter().zip(hints) {
+        let outline = gtk4::Box::new(gtk4::Orientation::Horizontal, 0);
+        outline.add_css_class("cell");
+        outline.set_size_request(cell.width, cell.height);
+
+        let label = gtk4::Label::new(Some(&hint.to_string()));
+        label.set_hexpand(true);
+        label.set_halign(gtk4::Align::Center);
+        label.set_valign(gtk4::Align::Center);
+        outline.append(&label);
+
+        fixed.put(&outline, cell.x as f64, cell.y as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_tile_the_region() {
+        let region = Region {
+            x: 10,
+            y: 20,
+            width: 1001,
+            height: 602,
+        };
+        for (rows, columns) in [(1, 1), (3, 3), (4, 7), (602, 1)] {
+            let cells = region.cells(rows, columns);
+            assert_eq!(cells.len(), (rows * columns) as usize);
+
+            // each cell starts where the one before it ends, and the last ends with the region
+            for row in cells.chunks(columns as usize) {
+                assert_eq!(row[0].x, region.x);
+                for pair in row.windows(2) {
+                    assert_eq!(pair[0].x + pair[0].width, pair[1].x);
+                    assert_eq!(pair[0].y, pair[1].y);
+                    assert_eq!(pair[0].height, pair[1].height);
+                }
+                let last = row.last().unwrap();
+                assert_eq!(last.x + last.width, region.x + region.width);
+            }
+            let rows: Vec<_> = cells.chunks(columns as usize).map(|row| row[0]).collect();
+            assert_eq!(rows[0].y, region.y);
+            for pair in rows.windows(2) {
+                assert_eq!(pair[0].y + pair[0].height, pair[1].y);
+            }
+            let last = rows.last().unwrap();
+            assert_eq!(last.y + last.height, region.y + region.height);
+
+            // the leftover pixels are spread out, not piled into one cell
+            let widths: Vec<_> = cells.iter().map(|cell| cell.width).collect();
+            let heights: Vec<_> = cells.iter().map(|cell| cell.height).collect();
+            assert!(widths.iter().max().unwrap() - widths.iter().min().unwrap() <= 1);
+            assert!(heights.iter().max().unwrap() - heights.iter().min().unwrap() <= 1);
+        }
+    }
+}
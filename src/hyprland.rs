@@ -0,0 +1,556 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    sync::mpsc,
+};
+
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::wm::{self, Dimension, Layout, Resize, WindowManager};
+
+/// Talks to Hyprland over the sockets in its instance directory, one request per connection.
+pub struct Hyprland {
+    socket_dir: PathBuf,
+}
+
+// Fields past the ones that identify things are optional, so that a client or monitor with a
+// field renamed by a newer Hyprland still shows up instead of stopping the overlay.
+
+#[derive(Deserialize, Default)]
+struct WorkspaceRef {
+    id: i64,
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Monitor {
+    id: i64,
+    name: String,
+    #[serde(default)]
+    x: i32,
+    #[serde(default)]
+    y: i32,
+    #[serde(default)]
+    width: i32,
+    #[serde(default)]
+    height: i32,
+    #[serde(default = "default_scale")]
+    scale: f64,
+    #[serde(default)]
+    transform: i32,
+    #[serde(default)]
+    active_workspace: WorkspaceRef,
+    #[serde(default)]
+    special_workspace: Option<WorkspaceRef>,
+    #[serde(default)]
+    focused: bool,
+}
+
+#[derive(Deserialize)]
+struct Workspace {
+    id: i64,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    monitor: String,
+}
+
+#[derive(Deserialize)]
+struct Client {
+    address: String,
+    #[serde(default = "default_mapped")]
+    mapped: bool,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    at: [i32; 2],
+    #[serde(default)]
+    size: [i32; 2],
+    #[serde(default)]
+    workspace: WorkspaceRef,
+    #[serde(default)]
+    floating: bool,
+    #[serde(default)]
+    monitor: i64,
+    #[serde(default)]
+    class: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    pid: Option<i32>,
+    #[serde(default)]
+    xwayland: bool,
+    #[serde(rename = "focusHistoryID", default)]
+    focus_history_id: Option<i64>,
+    // only reported by newer versions
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+fn default_mapped() -> bool {
+    true
+}
+
+/// Window addresses are pointers printed in hex, which make fine IDs.
+fn parse_address(address: &str) -> Option<i64> {
+    i64::from_str_radix(address.trim_start_matches("0x"), 16).ok()
+}
+
+fn address(id: i64) -> String {
+    format!("address:0x{:x}", id)
+}
+
+impl Monitor {
+    /// The monitor's geometry in layout coordinates, which are scaled and rotated.
+    fn rect(&self) -> wm::Rect {
+        let (width, height) = if self.transform % 2 == 1 {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        };
+        wm::Rect {
+            x: self.x,
+            y: self.y,
+            width: (width as f64 / self.scale).round() as i32,
+            height: (height as f64 / self.scale).round() as i32,
+        }
+    }
+}
+
+impl Hyprland {
+    pub fn connect() -> Self {
+        let signature = std::env::var("HYPRLAND_INSTANCE_SIGNATURE")
+            .expect("HYPRLAND_INSTANCE_SIGNATURE isn't set, is Hyprland running?");
+
+        // newer versions keep their sockets in the runtime directory, older ones in /tmp
+        let socket_dir = std::env::var("XDG_RUNTIME_DIR")
+            .map(|dir| PathBuf::from(dir).join("hypr").join(&signature))
+            .ok()
+            .filter(|dir| dir.exists())
+            .unwrap_or_else(|| PathBuf::from("/tmp/hypr").join(&signature));
+
+        Hyprland { socket_dir }
+    }
+
+    fn request(&self, request: &str) -> String {
+        let mut stream = UnixStream::connect(self.socket_dir.join(".socket.sock"))
+            .expect("failed to connect to Hyprland");
+        stream
+            .write_all(request.as_bytes())
+            .expect("failed to communicate with Hyprland");
+
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .expect("failed to communicate with Hyprland");
+        response
+    }
+
+    fn query<T: DeserializeOwned>(&self, request: &str) -> Option<T> {
+        match serde_json::from_str(&self.request(request)) {
+            Ok(response) => Some(response),
+            Err(err) => {
+                eprintln!("unexpected response to {} from Hyprland: {}", request, err);
+                None
+            }
+        }
+    }
+
+    /// Requests a list, e.g. `j/clients`, leaving out the entries that can't be read.
+    fn query_list<T: DeserializeOwned>(&self, request: &str) -> Vec<T> {
+        let entries: Vec<serde_json::Value> = self.query(request).unwrap_or_default();
+        entries
+            .into_iter()
+            .filter_map(|entry| match serde_json::from_value(entry) {
+                Ok(entry) => Some(entry),
+                Err(err) => {
+                    eprintln!("skipping an entry of {} from Hyprland: {}", request, err);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Runs several dispatchers in one go, in order.
+    fn dispatch(&self, dispatchers: &[String]) {
+        let batch = dispatchers
+            .iter()
+            .map(|dispatcher| format!("dispatch {}", dispatcher))
+            .collect::<Vec<_>>()
+            .join(";");
+        self.request(&format!("[[BATCH]]{}", batch));
+    }
+}
+
+impl WindowManager for Hyprland {
+    fn layout(&mut self) -> Layout {
+        let monitors: Vec<Monitor> = self.query_list("j/monitors");
+        let workspaces: Vec<Workspace> = self.query_list("j/workspaces");
+        let mut clients: Vec<Client> = self.query_list("j/clients");
+
+        let mut layout = Layout::default();
+        for monitor in monitors.iter() {
+            layout.outputs.push(wm::Output {
+                name: monitor.name.clone(),
+                rect: monitor.rect(),
+            });
+        }
+
+        for workspace in workspaces {
+            let monitor = monitors.iter().find(|m| m.name == workspace.monitor);
+            let shown = |m: &Monitor| {
+                m.active_workspace.id == workspace.id
+                    || m.special_workspace.as_ref().map(|s| s.id) == Some(workspace.id)
+            };
+            layout.workspaces.push(wm::Workspace {
+                id: workspace.id,
+                visible: monitor.is_some_and(shown),
+                focused: monitor
+                    .is_some_and(|m| m.focused && m.active_workspace.id == workspace.id),
+                name: workspace.name,
+                output: workspace.monitor,
+            });
+        }
+
+        // there is no tree to walk, so hints go left to right, then top to bottom
+        clients.sort_by_key(|client| (client.monitor, client.at[0], client.at[1]));
+        for client in clients {
+            let (Some(id), Some(monitor)) = (
+                parse_address(&client.address),
+                monitors.iter().find(|m| m.id == client.monitor),
+            ) else {
+                continue;
+            };
+            // unmapped windows and the inactive windows of groups can't be seen
+            if !client.mapped || client.hidden {
+                continue;
+            }
+
+            let visible = layout
+                .workspaces
+                .iter()
+                .any(|workspace| workspace.id == client.workspace.id && workspace.visible);
+            let (app_id, class) = if client.xwayland {
+                (None, Some(client.class))
+            } else {
+                (Some(client.class), None)
+            };
+            layout.windows.push(wm::Window {
+                id,
+                app_id,
+                class,
+                title: Some(client.title),
+                pid: client.pid,
+                rect: wm::Rect {
+                    x: client.at[0],
+                    y: client.at[1],
+                    width: client.size[0],
+                    height: client.size[1],
+                },
+                hint_position: (client.at[0], client.at[1]),
+                workspace: client.workspace.name,
+                output: wm::Output {
+                    name: monitor.name.clone(),
+                    rect: monitor.rect(),
+                },
+                floating: client.floating,
+                focused: client.focus_history_id == Some(0),
+                visible,
+                // tags stand in for marks, with dynamic ones suffixed by `*`
+                marks: client
+                    .tags
+                    .iter()
+                    .map(|tag| tag.trim_end_matches('*').to_string())
+                    .collect(),
+                parents: vec![],
+            });
+        }
+        layout
+    }
+
+    fn subscribe(&self) -> mpsc::Receiver<(wm::Event, Layout)> {
+        let (sender, receiver) = mpsc::channel();
+        let events = UnixStream::connect(self.socket_dir.join(".socket2.sock"))
+            .expect("failed to subscribe to Hyprland events");
+        let mut hyprland = Hyprland {
+            socket_dir: self.socket_dir.clone(),
+        };
+        std::thread::spawn(move || {
+            for line in BufReader::new(events).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let Some((name, data)) = line.split_once(">>") else {
+                    continue;
+                };
+                let event = match name {
+                    "activewindowv2" => match parse_address(data) {
+                        Some(id) => wm::Event::Focus(id),
+                        // nothing is focused
+                        None => wm::Event::Change,
+                    },
+                    "closewindow" => match parse_address(data) {
+                        Some(id) => wm::Event::Close(id),
+                        None => wm::Event::Change,
+                    },
+                    "openwindow" | "movewindow" | "changefloatingmode" | "fullscreen" | "pin"
+                    | "workspace" | "focusedmon" | "moveworkspace" | "monitoradded"
+                    | "monitorremoved" => wm::Event::Change,
+                    _ => continue,
+                };
+                if sender.send((event, hyprland.layout())).is_err() {
+                    break;
+                }
+            }
+        });
+        receiver
+    }
+
+    fn focus(&mut self, id: i64) {
+        self.dispatch(&[format!("focuswindow {}", address(id))]);
+    }
+
+    fn swap(&mut self, id: i64) {
+        self.dispatch(&[format!("swapwindow {}", address(id))]);
+    }
+
+    fn swap_pair(&mut self, source: i64, id: i64) {
+        // swapwindow always swaps the active window
+        self.dispatch(&[
+            format!("focuswindow {}", address(source)),
+            format!("swapwindow {}", address(id)),
+        ]);
+    }
+
+    fn bring(&mut self, id: i64) {
+        let Some(workspace) = self.query::<WorkspaceRef>("j/activeworkspace") else {
+            return;
+        };
+        self.dispatch(&[format!(
+            "movetoworkspacesilent {},{}",
+            workspace.id,
+            address(id)
+        )]);
+    }
+
+    fn kill(&mut self, id: i64) {
+        self.dispatch(&[format!("closewindow {}", address(id))]);
+    }
+
+    fn float(&mut self, id: i64) {
+        self.dispatch(&[format!("togglefloating {}", address(id))]);
+    }
+
+    fn fullscreen(&mut self, id: i64) {
+        // fullscreen always applies to the active window
+        self.dispatch(&[
+            format!("focuswindow {}", address(id)),
+            "fullscreen 0".to_string(),
+        ]);
+    }
+
+    fn sticky(&mut self, id: i64) {
+        self.dispatch(&[format!("pin {}", address(id))]);
+    }
+
+    fn scratchpad(&mut self, id: i64) {
+        self.dispatch(&[format!("movetoworkspacesilent special,{}", address(id))]);
+    }
+
+    fn resize(&mut self, id: i64, change: Resize, dimension: Dimension, amount: i32) {
+        let amount = match change {
+            Resize::Grow => amount,
+            Resize::Shrink => -amount,
+        };
+        let (x, y) = match dimension {
+            Dimension::Width => (amount, 0),
+            Dimension::Height => (0, amount),
+        };
+        self.dispatch(&[format!("resizewindowpixel {} {},{}", x, y, address(id))]);
+    }
+
+    fn place(&mut self, id: i64, rect: wm::Rect) {
+        self.dispatch(&[
+            format!("setfloating {}", address(id)),
+            format!(
+                "resizewindowpixel exact {} {},{}",
+                rect.width,
+                rect.height,
+                address(id)
+            ),
+            format!(
+                "movewindowpixel exact {} {},{}",
+                rect.x,
+                rect.y,
+                address(id)
+            ),
+        ]);
+    }
+
+    fn mark(&mut self, id: i64, name: &str) {
+        self.dispatch(&[format!("tagwindow +{} {}", name, address(id))]);
+    }
+
+    fn cursor_set(&mut self, x: i32, y: i32) {
+        self.dispatch(&[format!("movecursor {} {}", x, y)]);
+    }
+
+    fn click(&mut self, _button: &str) {
+        eprintln!("Hyprland has no way to click, only moving the cursor");
+    }
+
+    fn run_command(&mut self, command: &str) {
+        self.request(command);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wm::mock;
+    use std::sync::{Arc, Mutex};
+
+    const MONITORS: &str = r#"[
+        {"id": 0, "name": "DP-1", "x": 0, "y": 0, "width": 3840, "height": 2160, "scale": 2.0,
+         "transform": 0, "activeWorkspace": {"id": 1, "name": "1"},
+         "specialWorkspace": {"id": 0, "name": ""}, "focused": true, "someNewField": 1},
+        {"id": 1, "name": "HDMI-A-1", "x": 1920, "y": 0, "width": 1920, "height": 1080,
+         "activeWorkspace": {"id": 2, "name": "2"}, "focused": false}
+    ]"#;
+
+    const WORKSPACES: &str = r#"[
+        {"id": 1, "name": "1", "monitor": "DP-1"},
+        {"id": 2, "name": "2", "monitor": "HDMI-A-1"},
+        {"id": 3, "name": "3", "monitor": "DP-1"}
+    ]"#;
+
+    const CLIENTS: &str = r#"[
+        {"address": "0x20", "mapped": true, "hidden": false, "at": [960, 0], "size": [960, 1080],
+         "workspace": {"id": 1, "name": "1"}, "floating": false, "monitor": 0,
+         "class": "foot", "title": "vim", "pid": 20, "xwayland": false, "focusHistoryID": 1},
+        {"address": "0x10", "mapped": true, "hidden": false, "at": [0, 0], "size": [960, 1080],
+         "workspace": {"id": 1, "name": "1"}, "floating": false, "monitor": 0,
+         "class": "firefox", "title": "news", "pid": 10, "xwayland": true, "focusHistoryID": 0,
+         "tags": ["web", "pinned*"]},
+        {"address": "0x30", "at": [1920, 0], "size": [1920, 1080],
+         "workspace": {"id": 2, "name": "2"}, "monitor": 1, "class": "mpv"},
+        {"address": "0x40", "mapped": true, "hidden": true, "at": [0, 0], "size": [960, 1080],
+         "workspace": {"id": 1, "name": "1"}, "monitor": 0, "class": "grouped"},
+        {"address": "0x50", "at": [0, 0], "size": [1920, 1080],
+         "workspace": {"id": 3, "name": "3"}, "monitor": 0, "class": "slack"},
+        {"title": "no address"}
+    ]"#;
+
+    /// Serves canned replies on a Hyprland socket in a new directory, keeping every request.
+    fn fake_hyprland(name: &str) -> (Hyprland, Arc<Mutex<Vec<String>>>) {
+        let socket_dir = std::env::temp_dir().join(format!(
+            "sway-easyfocus-hyprland-{}-{}",
+            std::process::id(),
+            name
+        ));
+        std::fs::create_dir_all(&socket_dir).unwrap();
+        let requests = mock::fake_socket(
+            &socket_dir.join(".socket.sock"),
+            |stream| {
+                // requests are short enough to arrive in one read
+                let mut request = [0; 4096];
+                let len = stream.read(&mut request).unwrap();
+                String::from_utf8_lossy(&request[..len]).to_string()
+            },
+            |request| {
+                match request {
+                    "j/monitors" => MONITORS,
+                    "j/workspaces" => WORKSPACES,
+                    "j/clients" => CLIENTS,
+                    "j/activeworkspace" => r#"{"id": 1, "name": "1"}"#,
+                    _ => "ok",
+                }
+                .to_string()
+            },
+        );
+        (Hyprland { socket_dir }, requests)
+    }
+
+    #[test]
+    fn layout_comes_from_monitors_and_clients() {
+        let (mut hyprland, _) = fake_hyprland("layout");
+        let layout = hyprland.layout();
+
+        let outputs: Vec<_> = layout
+            .outputs
+            .iter()
+            .map(|output| (output.name.as_str(), output.rect))
+            .collect();
+        assert_eq!(
+            outputs,
+            [
+                (
+                    "DP-1",
+                    wm::Rect {
+                        x: 0,
+                        y: 0,
+                        width: 1920,
+                        height: 1080
+                    }
+                ),
+                (
+                    "HDMI-A-1",
+                    wm::Rect {
+                        x: 1920,
+                        y: 0,
+                        width: 1920,
+                        height: 1080
+                    }
+                ),
+            ]
+        );
+        let focused: Vec<_> = layout
+            .workspaces
+            .iter()
+            .filter(|workspace| workspace.focused)
+            .map(|workspace| workspace.name.as_str())
+            .collect();
+        assert_eq!(focused, ["1"]);
+
+        // hidden windows and ones without an address are left out
+        let ids: Vec<_> = layout.windows.iter().map(|window| window.id).collect();
+        assert_eq!(ids, [0x10, 0x50, 0x20, 0x30]);
+        let firefox = layout.window(0x10).unwrap();
+        assert_eq!(firefox.class.as_deref(), Some("firefox"));
+        assert_eq!(firefox.app_id, None);
+        assert!(firefox.focused && firefox.visible);
+        assert_eq!(firefox.marks, ["web", "pinned"]);
+        let mpv = layout.window(0x30).unwrap();
+        assert_eq!(mpv.app_id.as_deref(), Some("mpv"));
+        assert_eq!(mpv.output.name, "HDMI-A-1");
+        assert!(!mpv.focused && mpv.visible);
+        assert!(!layout.window(0x50).unwrap().visible);
+    }
+
+    #[test]
+    fn dispatchers_are_batched() {
+        let (mut hyprland, requests) = fake_hyprland("dispatch");
+        hyprland.focus(0x10);
+        hyprland.swap_pair(0x10, 0x20);
+        hyprland.resize(0x20, Resize::Shrink, Dimension::Height, 30);
+        hyprland.bring(0x30);
+
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "[[BATCH]]dispatch focuswindow address:0x10",
+                "[[BATCH]]dispatch focuswindow address:0x10;dispatch swapwindow address:0x20",
+                "[[BATCH]]dispatch resizewindowpixel 0 -30,address:0x20",
+                "j/activeworkspace",
+                "[[BATCH]]dispatch movetoworkspacesilent 1,address:0x30",
+            ]
+        );
+    }
+}
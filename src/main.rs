@@ -1,15 +1,22 @@
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory, Parser};
 use cli::Args;
 use figment::{
     providers::{Format, Yaml},
     Figment,
 };
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 mod cli;
+mod daemon;
+mod grid;
+mod hyprland;
+mod niri;
 mod sway;
 mod ui;
 mod utils;
+mod wlroots;
+mod wm;
+mod x11;
 
 fn parse_config() -> Arc<Args> {
     // there is probably a way better way to do this...
@@ -27,11 +34,26 @@ fn parse_config() -> Arc<Args> {
     }
     let cli_args = Args::parse();
     args.merge(&cli_args);
+    if let Err(err) = args.validate() {
+        Args::command()
+            .error(ErrorKind::ValueValidation, err)
+            .exit();
+    }
     Arc::new(args)
 }
 
 fn main() {
     let args = parse_config();
-    let conn = Arc::new(Mutex::new(sway::acquire_connection()));
-    ui::run_ui(conn, args);
+    // the daemon talks to sway on the trigger's behalf
+    if let Some(cli::Command::Trigger { .. } | cli::Command::Previous) = args.command {
+        daemon::trigger();
+        return;
+    }
+
+    let wm = wm::connect(args.backend.unwrap(), args.seat.as_ref().unwrap());
+    if let Some(cli::Command::Daemon) = args.command {
+        daemon::run_daemon(wm, args);
+    } else {
+        ui::run_ui(wm, args);
+    }
 }
@@ -0,0 +1,554 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    sync::mpsc,
+};
+
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+
+use crate::wm::{self, Dimension, Layout, Resize, WindowManager};
+
+/// niri's default gap between columns and tiles, which its IPC doesn't report.
+const GAP: i32 = 16;
+/// Room left for the label of a column scrolled off the right edge.
+const EDGE_MARGIN: i32 = 48;
+/// How far apart the labels of columns scrolled off the same edge are stacked.
+const EDGE_SPACING: i32 = 40;
+
+/// Talks to niri over `$NIRI_SOCKET`, one JSON request per connection.
+pub struct Niri {
+    socket: String,
+}
+
+#[derive(Deserialize)]
+struct LogicalOutput {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+#[derive(Deserialize)]
+struct Output {
+    name: String,
+    logical: Option<LogicalOutput>,
+}
+
+#[derive(Deserialize)]
+struct Workspace {
+    id: u64,
+    idx: u8,
+    name: Option<String>,
+    output: Option<String>,
+    is_active: bool,
+    is_focused: bool,
+    /// Missing from older versions of niri, which then start from the first column
+    #[serde(default)]
+    active_window_id: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+struct WindowLayout {
+    /// Column and tile within the column, counting from 1
+    pos_in_scrolling_layout: Option<(usize, usize)>,
+    tile_size: (f64, f64),
+    tile_pos_in_workspace_view: Option<(f64, f64)>,
+}
+
+#[derive(Deserialize)]
+struct Window {
+    id: u64,
+    title: Option<String>,
+    app_id: Option<String>,
+    pid: Option<i32>,
+    workspace_id: Option<u64>,
+    is_focused: bool,
+    /// Missing from versions of niri without floating windows, so tiled
+    #[serde(default)]
+    is_floating: bool,
+    /// Missing from versions of niri from before window layouts were reported
+    #[serde(default)]
+    layout: WindowLayout,
+}
+
+impl Workspace {
+    fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.idx.to_string())
+    }
+}
+
+/// Where each window of a workspace's scrolling layout is in the output's view, which niri only
+/// reports for floating windows. Columns are laid out from the one holding the active window,
+/// assumed to be at the left of the view.
+fn tile_positions(windows: &[&Window], active_window_id: Option<u64>) -> HashMap<u64, (i32, i32)> {
+    let mut columns: Vec<Vec<&Window>> = vec![];
+    for window in windows {
+        if let Some((column, _)) = window.layout.pos_in_scrolling_layout {
+            if columns.len() < column {
+                columns.resize(column, vec![]);
+            }
+            columns[column - 1].push(window);
+        }
+    }
+    for column in columns.iter_mut() {
+        column.sort_by_key(|window| window.layout.pos_in_scrolling_layout);
+    }
+
+    let active_column = columns
+        .iter()
+        .position(|column| column.iter().any(|w| Some(w.id) == active_window_id))
+        .unwrap_or(0);
+    let width = |column: &Vec<&Window>| {
+        column
+            .iter()
+            .map(|window| window.layout.tile_size.0 as i32)
+            .max()
+            .unwrap_or(0)
+    };
+
+    let mut x = GAP
+        - columns[..active_column]
+            .iter()
+            .map(|column| width(column) + GAP)
+            .sum::<i32>();
+    let mut positions = HashMap::new();
+    for column in columns.iter() {
+        let mut y = GAP;
+        for window in column {
+            positions.insert(window.id, (x, y));
+            y += window.layout.tile_size.1 as i32 + GAP;
+        }
+        x += width(column) + GAP;
+    }
+    positions
+}
+
+impl Niri {
+    pub fn connect() -> Self {
+        let socket = std::env::var("NIRI_SOCKET").expect("NIRI_SOCKET isn't set, is niri running?");
+        Niri { socket }
+    }
+
+    fn send(&self, request: &Value) -> UnixStream {
+        let mut stream = UnixStream::connect(&self.socket).expect("failed to connect to niri");
+        writeln!(stream, "{}", request).expect("failed to communicate with niri");
+        stream
+    }
+
+    /// Sends the request, returning the reply's `Ok` value.
+    fn request(&self, request: Value) -> Value {
+        let stream = self.send(&request);
+        let mut reply = String::new();
+        BufReader::new(stream)
+            .read_line(&mut reply)
+            .expect("failed to communicate with niri");
+
+        let mut reply: Value = serde_json::from_str(&reply).expect("unexpected reply from niri");
+        if let Some(err) = reply.get("Err") {
+            eprintln!("niri: {}", err);
+        }
+        reply["Ok"].take()
+    }
+
+    /// Requests one of niri's lists, e.g. `Windows`.
+    fn query<T: DeserializeOwned>(&self, name: &str) -> T {
+        let mut reply = self.request(json!(name));
+        serde_json::from_value(reply[name].take()).expect("unexpected reply from niri")
+    }
+
+    fn action(&self, action: Value) {
+        self.request(json!({ "Action": action }));
+    }
+
+    fn unsupported(&self, what: &str) {
+        eprintln!("niri has no way to {}", what);
+    }
+}
+
+impl WindowManager for Niri {
+    fn layout(&mut self) -> Layout {
+        let outputs: HashMap<String, Output> = self.query("Outputs");
+        let workspaces: Vec<Workspace> = self.query("Workspaces");
+        let windows: Vec<Window> = self.query("Windows");
+
+        let mut layout = Layout::default();
+        for output in outputs.values() {
+            if let Some(logical) = &output.logical {
+                layout.outputs.push(wm::Output {
+                    name: output.name.clone(),
+                    rect: wm::Rect {
+                        x: logical.x,
+                        y: logical.y,
+                        width: logical.width,
+                        height: logical.height,
+                    },
+                });
+            }
+        }
+        // outputs come in a map, so keep them in a stable order for hints
+        layout
+            .outputs
+            .sort_by_key(|output| (output.rect.x, output.rect.y));
+
+        let mut workspaces = workspaces;
+        workspaces.sort_by_key(|workspace| (workspace.output.clone(), workspace.idx));
+        for workspace in workspaces.iter() {
+            let Some(output) = layout
+                .outputs
+                .iter()
+                .find(|output| Some(&output.name) == workspace.output.as_ref())
+                .cloned()
+            else {
+                continue;
+            };
+            layout.workspaces.push(wm::Workspace {
+                id: workspace.id as i64,
+                name: workspace.name(),
+                output: output.name.clone(),
+                visible: workspace.is_active,
+                focused: workspace.is_focused,
+            });
+
+            let mut on_workspace: Vec<&Window> = windows
+                .iter()
+                .filter(|window| window.workspace_id == Some(workspace.id))
+                .collect();
+            // hints go column by column, then to the floating windows
+            on_workspace.sort_by_key(|window| {
+                (
+                    window.is_floating,
+                    window.layout.pos_in_scrolling_layout,
+                    window.id,
+                )
+            });
+            let tiles = tile_positions(&on_workspace, workspace.active_window_id);
+            // the columns off each edge, left then right, in the order their labels are stacked
+            let mut offscreen: [Vec<Option<usize>>; 2] = [vec![], vec![]];
+
+            for window in on_workspace {
+                let (x, y) = match window.layout.tile_pos_in_workspace_view {
+                    Some((x, y)) => (x as i32, y as i32),
+                    None => tiles.get(&window.id).copied().unwrap_or((GAP, GAP)),
+                };
+                let (width, height) = (
+                    window.layout.tile_size.0 as i32,
+                    window.layout.tile_size.1 as i32,
+                );
+
+                // columns scrolled out of view keep their labels at the nearest edge, one below
+                // the other
+                let onscreen = x + width > 0 && x < output.rect.width;
+                let hint_x = x.clamp(0, (output.rect.width - EDGE_MARGIN).max(0));
+                let mut hint_y = y;
+                if !onscreen {
+                    let edge = &mut offscreen[usize::from(x > 0)];
+                    // floating windows have no column, so each gets a place of its own
+                    let column = window
+                        .layout
+                        .pos_in_scrolling_layout
+                        .map(|(column, _)| column);
+                    let place = match edge.iter().position(|&c| c.is_some() && c == column) {
+                        Some(place) => place,
+                        None => {
+                            edge.push(column);
+                            edge.len() - 1
+                        }
+                    };
+                    hint_y += place as i32 * EDGE_SPACING;
+                }
+
+                layout.windows.push(wm::Window {
+                    id: window.id as i64,
+                    app_id: window.app_id.clone(),
+                    class: None,
+                    title: window.title.clone(),
+                    pid: window.pid,
+                    rect: wm::Rect {
+                        x: output.rect.x + x,
+                        y: output.rect.y + y,
+                        width,
+                        height,
+                    },
+                    hint_position: (output.rect.x + hint_x, output.rect.y + hint_y),
+                    workspace: workspace.name(),
+                    output: output.clone(),
+                    floating: window.is_floating,
+                    focused: window.is_focused,
+                    visible: workspace.is_active && onscreen,
+                    marks: vec![],
+                    parents: vec![],
+                });
+            }
+        }
+        layout
+    }
+
+    fn subscribe(&self) -> mpsc::Receiver<(wm::Event, Layout)> {
+        let (sender, receiver) = mpsc::channel();
+        let events = self.send(&json!("EventStream"));
+        let mut niri = Niri {
+            socket: self.socket.clone(),
+        };
+        std::thread::spawn(move || {
+            // the first line only acknowledges the request
+            for line in BufReader::new(events).lines().skip(1) {
+                let Ok(line) = line else {
+                    break;
+                };
+                let Ok(Value::Object(event)) = serde_json::from_str(&line) else {
+                    continue;
+                };
+                let Some((name, data)) = event.into_iter().next() else {
+                    continue;
+                };
+                let event = match name.as_str() {
+                    "WindowFocusChanged" => match data["id"].as_i64() {
+                        Some(id) => wm::Event::Focus(id),
+                        None => wm::Event::Change,
+                    },
+                    "WindowClosed" => match data["id"].as_i64() {
+                        Some(id) => wm::Event::Close(id),
+                        None => wm::Event::Change,
+                    },
+                    "WindowsChanged"
+                    | "WindowOpenedOrChanged"
+                    | "WindowLayoutsChanged"
+                    | "WorkspacesChanged"
+                    | "WorkspaceActivated"
+                    | "WorkspaceActiveWindowChanged" => wm::Event::Change,
+                    _ => continue,
+                };
+                if sender.send((event, niri.layout())).is_err() {
+                    break;
+                }
+            }
+        });
+        receiver
+    }
+
+    fn focus(&mut self, id: i64) {
+        self.action(json!({ "FocusWindow": { "id": id } }));
+    }
+
+    fn swap(&mut self, _id: i64) {
+        self.unsupported("swap windows");
+    }
+
+    fn swap_pair(&mut self, _source: i64, _id: i64) {
+        self.unsupported("swap windows");
+    }
+
+    fn bring(&mut self, id: i64) {
+        let layout = self.layout();
+        if let Some(workspace) = layout.focused_workspace() {
+            self.action(json!({ "MoveWindowToWorkspace": {
+                "window_id": id,
+                "reference": { "Id": workspace.id },
+                "focus": false,
+            } }));
+        }
+    }
+
+    fn kill(&mut self, id: i64) {
+        self.action(json!({ "CloseWindow": { "id": id } }));
+    }
+
+    fn float(&mut self, id: i64) {
+        self.action(json!({ "ToggleWindowFloating": { "id": id } }));
+    }
+
+    fn fullscreen(&mut self, id: i64) {
+        self.action(json!({ "FullscreenWindow": { "id": id } }));
+    }
+
+    fn sticky(&mut self, _id: i64) {
+        self.unsupported("make windows sticky");
+    }
+
+    fn scratchpad(&mut self, _id: i64) {
+        self.unsupported("hide windows in a scratchpad");
+    }
+
+    fn resize(&mut self, id: i64, change: Resize, dimension: Dimension, amount: i32) {
+        let amount = match change {
+            Resize::Grow => amount,
+            Resize::Shrink => -amount,
+        };
+        let action = match dimension {
+            Dimension::Width => "SetWindowWidth",
+            Dimension::Height => "SetWindowHeight",
+        };
+        self.action(json!({ action: { "id": id, "change": { "AdjustFixed": amount } } }));
+    }
+
+    fn place(&mut self, id: i64, rect: wm::Rect) {
+        // floating windows are moved within their output's view
+        let layout = self.layout();
+        let Some(output) = layout.window(id).map(|window| &window.output) else {
+            return;
+        };
+        self.action(json!({ "MoveWindowToFloating": { "id": id } }));
+        self.action(
+            json!({ "SetWindowWidth": { "id": id, "change": { "SetFixed": rect.width } } }),
+        );
+        self.action(
+            json!({ "SetWindowHeight": { "id": id, "change": { "SetFixed": rect.height } } }),
+        );
+        self.action(json!({ "MoveFloatingWindow": {
+            "id": id,
+            "x": { "SetFixed": (rect.x - output.rect.x) as f64 },
+            "y": { "SetFixed": (rect.y - output.rect.y) as f64 },
+        } }));
+    }
+
+    fn mark(&mut self, _id: i64, _name: &str) {
+        self.unsupported("mark windows");
+    }
+
+    fn cursor_set(&mut self, _x: i32, _y: i32) {
+        self.unsupported("move the cursor");
+    }
+
+    fn click(&mut self, _button: &str) {
+        self.unsupported("click");
+    }
+
+    fn run_command(&mut self, command: &str) {
+        match serde_json::from_str(command) {
+            Ok(request) => {
+                self.request(request);
+            }
+            Err(err) => eprintln!("niri requests are JSON: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wm::mock;
+    use std::sync::{Arc, Mutex};
+
+    /// A tiled window of the single workspace, 1000 pixels wide.
+    fn tile(id: u64, column: usize, tile: usize) -> Value {
+        json!({
+            "id": id, "title": format!("window {}", id), "app_id": "foot", "pid": id,
+            "workspace_id": 1, "is_focused": id == 1, "is_floating": false,
+            "layout": {
+                "pos_in_scrolling_layout": [column, tile],
+                "tile_size": [1000.0, 500.0],
+                "tile_pos_in_workspace_view": null,
+                "window_size": [1000, 500],
+            },
+        })
+    }
+
+    fn reply(request: &Value) -> Value {
+        match request.as_str() {
+            Some("Outputs") => json!({ "Outputs": { "DP-1": {
+                "name": "DP-1", "make": "", "model": "",
+                "logical": { "x": 0, "y": 0, "width": 1920, "height": 1080, "scale": 1.0 },
+            } } }),
+            Some("Workspaces") => json!({ "Workspaces": [{
+                "id": 1, "idx": 1, "name": null, "output": "DP-1",
+                "is_active": true, "is_focused": true, "active_window_id": 1,
+            }] }),
+            Some("Windows") => json!({ "Windows": [
+                tile(1, 1, 1),
+                tile(2, 2, 1),
+                tile(3, 3, 1),
+                tile(4, 3, 2),
+                tile(5, 4, 1),
+            ] }),
+            _ => json!("Handled"),
+        }
+    }
+
+    /// Serves canned replies on a niri socket, keeping every request.
+    fn fake_niri(name: &str) -> (Niri, Arc<Mutex<Vec<String>>>) {
+        let socket = std::env::temp_dir().join(format!(
+            "sway-easyfocus-niri-{}-{}.sock",
+            std::process::id(),
+            name
+        ));
+        let requests = mock::fake_socket(
+            &socket,
+            |stream| {
+                let mut request = String::new();
+                BufReader::new(stream).read_line(&mut request).unwrap();
+                request
+            },
+            |request| {
+                let request: Value = serde_json::from_str(request).unwrap();
+                format!("{}\n", json!({ "Ok": reply(&request) }))
+            },
+        );
+        let socket = socket.to_str().unwrap().to_string();
+        (Niri { socket }, requests)
+    }
+
+    #[test]
+    fn offscreen_columns_are_stacked_at_the_edge() {
+        let (mut niri, _) = fake_niri("layout");
+        let layout = niri.layout();
+
+        let windows: Vec<_> = layout
+            .windows
+            .iter()
+            .map(|window| (window.id, window.hint_position, window.visible))
+            .collect();
+        let edge = 1920 - EDGE_MARGIN;
+        assert_eq!(
+            windows,
+            [
+                (1, (GAP, GAP), true),
+                (2, (1000 + 2 * GAP, GAP), true),
+                (3, (edge, GAP), false),
+                (4, (edge, 500 + 2 * GAP), false),
+                (5, (edge, GAP + EDGE_SPACING), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn older_versions_are_understood() {
+        let workspace: Workspace = serde_json::from_value(json!({
+            "id": 1, "idx": 1, "name": null, "output": "DP-1", "is_active": true, "is_focused": true,
+        }))
+        .unwrap();
+        assert_eq!(workspace.active_window_id, None);
+
+        let window: Window = serde_json::from_value(json!({
+            "id": 1, "title": null, "app_id": null, "pid": null, "workspace_id": 1,
+            "is_focused": true,
+        }))
+        .unwrap();
+        assert!(!window.is_floating);
+        assert_eq!(window.layout.pos_in_scrolling_layout, None);
+    }
+
+    #[test]
+    fn actions_are_requested() {
+        let (mut niri, requests) = fake_niri("actions");
+        niri.focus(3);
+        niri.resize(3, Resize::Shrink, Dimension::Width, 20);
+
+        let requests: Vec<Value> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| serde_json::from_str(request).unwrap())
+            .collect();
+        assert_eq!(
+            requests,
+            [
+                json!({ "Action": { "FocusWindow": { "id": 3 } } }),
+                json!({ "Action": { "SetWindowWidth": {
+                    "id": 3, "change": { "AdjustFixed": -20 },
+                } } }),
+            ]
+        );
+    }
+}
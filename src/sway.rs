@@ -1,26 +1,83 @@
-use std::{
-    collections::VecDeque,
-    sync::{Arc, Mutex},
-};
+use std::{collections::VecDeque, os::unix::net::UnixStream, sync::mpsc};
 
 use swayipc::*;
 
-pub fn acquire_connection() -> Connection {
-    swayipc::Connection::new().expect("failed to connect to sway")
+use crate::wm::{self, Dimension, Layout, Resize, WindowManager};
+
+/// Which compositor is on the other end, since i3 speaks the same IPC with a few differences.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flavor {
+    Sway,
+    I3,
+}
+
+pub struct Sway {
+    conn: Connection,
+    flavor: Flavor,
+    /// The seat whose cursor is moved, `-` for the current one
+    seat: String,
 }
 
-pub fn get_tree(conn: Arc<Mutex<Connection>>) -> Node {
-    let mut conn_lock = conn.lock().unwrap();
-    conn_lock
-        .get_tree()
-        .expect("failed to communicate with sway")
+impl Sway {
+    pub fn connect(seat: &str) -> Self {
+        Sway {
+            conn: acquire_connection(Flavor::Sway),
+            flavor: Flavor::Sway,
+            seat: seat.to_string(),
+        }
+    }
+
+    pub fn connect_i3() -> Self {
+        Sway {
+            conn: acquire_connection(Flavor::I3),
+            flavor: Flavor::I3,
+            // X11 has a single pointer
+            seat: "-".to_string(),
+        }
+    }
+
+    fn get_tree(&mut self) -> Node {
+        self.conn
+            .get_tree()
+            .expect("failed to communicate with sway")
+    }
+}
+
+fn acquire_connection(flavor: Flavor) -> Connection {
+    match flavor {
+        Flavor::Sway => swayipc::Connection::new().expect("failed to connect to sway"),
+        // swayipc would pick sway's socket over a running i3's
+        Flavor::I3 => {
+            let path = std::env::var("I3SOCK")
+                .ok()
+                .or_else(|| {
+                    let output = std::process::Command::new("i3")
+                        .arg("--get-socketpath")
+                        .output()
+                        .ok()?;
+                    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+                })
+                .expect("could not find i3's socket, is i3 running?");
+            UnixStream::connect(path)
+                .expect("failed to connect to i3")
+                .into()
+        }
+    }
+}
+
+fn to_rect(rect: &swayipc::Rect) -> wm::Rect {
+    wm::Rect {
+        x: rect.x,
+        y: rect.y,
+        width: rect.width,
+        height: rect.height,
+    }
 }
 
 // Get all output nodes, focused or not
-pub fn get_all_output_nodes(conn: Arc<Mutex<Connection>>) -> Vec<Node> {
+fn get_all_output_nodes(root_node: Node) -> Vec<Node> {
     let mut output_nodes = vec![];
     let mut q = VecDeque::new();
-    let root_node = get_tree(conn);
 
     q.push_back(root_node);
 
@@ -47,27 +104,45 @@ pub fn get_all_output_nodes(conn: Arc<Mutex<Connection>>) -> Vec<Node> {
     output_nodes
 }
 
-pub fn get_focused_workspace(output: &Node) -> Node {
+/// The output's workspaces, which i3 keeps in a content container between its docks.
+fn get_workspaces(output: &Node) -> Vec<&Node> {
+    let mut workspaces = vec![];
+    for node in output.nodes.iter() {
+        if node.node_type == NodeType::Workspace {
+            workspaces.push(node);
+        } else {
+            workspaces.extend(get_workspaces(node));
+        }
+    }
+    workspaces
+}
+
+fn get_focused_workspace(output: &Node) -> Node {
     output
         .clone()
         .find_focused(|n| n.node_type == swayipc::NodeType::Workspace)
         .expect("could not find focused workspace")
 }
 
-pub fn get_all_windows(workspace: &Node) -> Vec<Node> {
-    let mut nodes = vec![];
+fn get_all_windows(
+    flavor: Flavor,
+    output: &wm::Output,
+    workspace: &Node,
+    visible: bool,
+) -> Vec<wm::Window> {
+    let mut windows = vec![];
 
+    // each node is queued along with the containers above it and its parent's geometry
     let mut q = VecDeque::new();
-    q.push_back(workspace.clone());
+    q.push_back((workspace.clone(), vec![], workspace.rect));
     while !q.is_empty() {
         // we can unwrap because we know that the queue is not empty
-        let node = q.pop_back().unwrap();
+        let (node, parents, parent_rect) = q.pop_back().unwrap();
 
-        // if we have a window
-        if (node.node_type == NodeType::Con || node.node_type == NodeType::FloatingCon)
-            && node.nodes.is_empty()
-        {
-            nodes.push(node.clone());
+        // the workspace isn't a parent we can focus as a container
+        let mut child_parents = parents.clone();
+        if node.node_type != NodeType::Workspace {
+            child_parents.push(node.id);
         }
 
         // tiled/tabbed/stacked nodes
@@ -82,30 +157,288 @@ pub fn get_all_windows(workspace: &Node) -> Vec<Node> {
                 // the stacked container.
                 c.deco_rect.height *= node.nodes.len() as i32;
             }
-            q.push_back(c);
+            q.push_back((c, child_parents.clone(), node.rect));
         }
 
         // floating nodes
-        for child in node.floating_nodes {
-            q.push_back(child.clone());
+        for child in &node.floating_nodes {
+            q.push_back((child.clone(), child_parents.clone(), node.rect));
+        }
+
+        // if we have a window
+        if (node.node_type == NodeType::Con || node.node_type == NodeType::FloatingCon)
+            && node.nodes.is_empty()
+        {
+            // i3 keeps empty placeholders
+            if flavor == Flavor::I3 && node.window.is_none() {
+                continue;
+            }
+
+            let hint_position = hint_position(flavor, &node, &parent_rect);
+            windows.push(to_window(
+                node,
+                workspace.name.clone().unwrap_or_default(),
+                output,
+                parents,
+                hint_position,
+                visible,
+            ));
         }
     }
 
-    nodes.reverse();
-    // dbg!(&nodes);
-    nodes
+    windows.reverse();
+    // dbg!(&windows);
+    windows
+}
+
+/// Where the window's title bar is, or its top-left corner without one.
+fn hint_position(flavor: Flavor, node: &Node, parent_rect: &swayipc::Rect) -> (i32, i32) {
+    let rect = node.rect;
+    let window_rect = node.window_rect;
+    let deco_rect = node.deco_rect;
+
+    match flavor {
+        // the title bar is drawn above `rect`
+        Flavor::Sway => {
+            let hint_x = rect.x + window_rect.x + deco_rect.x;
+            let hint_y = rect.y - deco_rect.height
+                + if node.layout == NodeLayout::Stacked {
+                    deco_rect.y
+                } else {
+                    0
+                };
+            (hint_x, hint_y)
+        }
+        // the title bar is placed relative to the parent, which also holds tabs and stacks
+        Flavor::I3 if deco_rect.height > 0 => {
+            (parent_rect.x + deco_rect.x, parent_rect.y + deco_rect.y)
+        }
+        Flavor::I3 => (rect.x, rect.y),
+    }
+}
+
+fn to_window(
+    node: Node,
+    workspace: String,
+    output: &wm::Output,
+    parents: Vec<i64>,
+    hint_position: (i32, i32),
+    workspace_visible: bool,
+) -> wm::Window {
+    wm::Window {
+        id: node.id,
+        class: node
+            .window_properties
+            .as_ref()
+            .and_then(|props| props.class.clone()),
+        title: node.name,
+        pid: node.pid,
+        rect: to_rect(&node.rect),
+        hint_position,
+        workspace,
+        output: output.clone(),
+        // i3 marks the window inside the floating container
+        floating: node.node_type == NodeType::FloatingCon
+            || matches!(node.floating, Some(Floating::AutoOn | Floating::UserOn)),
+        focused: node.focused,
+        // i3 doesn't say, so assume windows on a shown workspace can be seen
+        visible: node.visible.unwrap_or(workspace_visible),
+        marks: node.marks,
+        parents,
+        app_id: node.app_id,
+    }
+}
+
+fn to_layout(flavor: Flavor, tree: Node) -> Layout {
+    let focused_workspace = tree
+        .find_focused_as_ref(|n| n.node_type == NodeType::Workspace)
+        .map(|n| n.id);
+
+    let mut layout = Layout::default();
+    for output_node in get_all_output_nodes(tree) {
+        let output = wm::Output {
+            name: output_node.name.clone().unwrap_or_default(),
+            rect: to_rect(&output_node.rect),
+        };
+        let visible_workspace = get_focused_workspace(&output_node).id;
+
+        for workspace in get_workspaces(&output_node) {
+            let visible = workspace.id == visible_workspace;
+            layout.workspaces.push(wm::Workspace {
+                id: workspace.id,
+                name: workspace.name.clone().unwrap_or_default(),
+                output: output.name.clone(),
+                visible,
+                focused: Some(workspace.id) == focused_workspace,
+            });
+            layout
+                .windows
+                .extend(get_all_windows(flavor, &output, workspace, visible));
+        }
+        layout.outputs.push(output);
+    }
+    layout
 }
 
-pub fn focus(conn: Arc<Mutex<Connection>>, con_id: i64) {
-    let mut conn_lock = conn.lock().unwrap();
-    conn_lock
-        .run_command(format!("[con_id={}] focus", con_id))
-        .expect("failed to focus container");
+impl WindowManager for Sway {
+    fn layout(&mut self) -> Layout {
+        to_layout(self.flavor, self.get_tree())
+    }
+
+    fn subscribe(&self) -> mpsc::Receiver<(wm::Event, Layout)> {
+        let (sender, receiver) = mpsc::channel();
+        let flavor = self.flavor;
+        let seat = self.seat.clone();
+        let events = acquire_connection(flavor)
+            .subscribe([EventType::Window, EventType::Workspace])
+            .expect("failed to subscribe to sway events");
+        std::thread::spawn(move || {
+            let mut sway = Sway {
+                conn: acquire_connection(flavor),
+                flavor,
+                seat,
+            };
+            for event in events {
+                let event = match event {
+                    Ok(Event::Window(event)) => match event.change {
+                        WindowChange::Focus => wm::Event::Focus(event.container.id),
+                        WindowChange::Close => wm::Event::Close(event.container.id),
+                        _ => wm::Event::Change,
+                    },
+                    _ => wm::Event::Change,
+                };
+                if sender.send((event, sway.layout())).is_err() {
+                    break;
+                }
+            }
+        });
+        receiver
+    }
+
+    fn focus(&mut self, con_id: i64) {
+        self.run_command(&format!("[con_id={}] focus", con_id));
+    }
+
+    fn swap(&mut self, con_id: i64) {
+        self.run_command(&format!("swap container with con_id {}", con_id));
+    }
+
+    fn swap_pair(&mut self, source: i64, con_id: i64) {
+        self.run_command(&format!(
+            "[con_id={}] swap container with con_id {}",
+            source, con_id
+        ));
+    }
+
+    fn bring(&mut self, con_id: i64) {
+        let workspace = match self.flavor {
+            Flavor::Sway => "current".to_string(),
+            // i3 has no name for the current workspace
+            Flavor::I3 => {
+                let workspaces = self
+                    .conn
+                    .get_workspaces()
+                    .expect("failed to communicate with i3");
+                let focused = workspaces
+                    .iter()
+                    .find(|workspace| workspace.focused)
+                    .expect("could not find focused workspace");
+                format!("\"{}\"", focused.name.replace('"', "\\\""))
+            }
+        };
+        self.run_command(&format!(
+            "[con_id={}] move container to workspace {}",
+            con_id, workspace
+        ));
+    }
+
+    fn kill(&mut self, con_id: i64) {
+        self.run_command(&format!("[con_id={}] kill", con_id));
+    }
+
+    fn float(&mut self, con_id: i64) {
+        self.run_command(&format!("[con_id={}] floating toggle", con_id));
+    }
+
+    fn fullscreen(&mut self, con_id: i64) {
+        self.run_command(&format!("[con_id={}] fullscreen toggle", con_id));
+    }
+
+    fn sticky(&mut self, con_id: i64) {
+        self.run_command(&format!("[con_id={}] sticky toggle", con_id));
+    }
+
+    fn scratchpad(&mut self, con_id: i64) {
+        self.run_command(&format!("[con_id={}] move scratchpad", con_id));
+    }
+
+    fn resize(&mut self, con_id: i64, change: Resize, dimension: Dimension, amount: i32) {
+        let change = match change {
+            Resize::Grow => "grow",
+            Resize::Shrink => "shrink",
+        };
+        let dimension = match dimension {
+            Dimension::Width => "width",
+            Dimension::Height => "height",
+        };
+        self.run_command(&format!(
+            "[con_id={}] resize {} {} {} px",
+            con_id, change, dimension, amount
+        ));
+    }
+
+    fn place(&mut self, con_id: i64, rect: wm::Rect) {
+        let wm::Rect {
+            x,
+            y,
+            width,
+            height,
+        } = rect;
+        self.run_command(&format!(
+            "[con_id={con_id}] floating enable; \
+             [con_id={con_id}] resize set {width} px {height} px; \
+             [con_id={con_id}] move absolute position {x} px {y} px"
+        ));
+    }
+
+    fn mark(&mut self, con_id: i64, name: &str) {
+        self.run_command(&format!("[con_id={}] mark --add {}", con_id, name));
+    }
+
+    fn cursor_set(&mut self, x: i32, y: i32) {
+        match self.flavor {
+            Flavor::Sway => {
+                let command = format!("seat {} cursor set {} {}", self.seat, x, y);
+                self.run_command(&command);
+            }
+            // i3 leaves the pointer to X11
+            Flavor::I3 => xdotool(&["mousemove", &x.to_string(), &y.to_string()]),
+        }
+    }
+
+    fn click(&mut self, button: &str) {
+        match self.flavor {
+            Flavor::Sway => {
+                let seat = &self.seat;
+                let command = format!(
+                    "seat {seat} cursor press {button}; seat {seat} cursor release {button}"
+                );
+                self.run_command(&command);
+            }
+            // xdotool numbers its buttons like X11 does
+            Flavor::I3 => xdotool(&["click", button.trim_start_matches("button")]),
+        }
+    }
+
+    fn run_command(&mut self, command: &str) {
+        self.conn
+            .run_command(command)
+            .expect("failed to communicate with sway");
+    }
 }
 
-pub fn swap(conn: Arc<Mutex<Connection>>, con_id: i64) {
-    let mut conn_lock = conn.lock().unwrap();
-    conn_lock
-        .run_command(format!("swap container with con_id {}", con_id))
-        .expect("failed to swap container");
+fn xdotool(args: &[&str]) {
+    if let Err(err) = std::process::Command::new("xdotool").args(args).status() {
+        eprintln!("failed to run xdotool: {}", err);
+    }
 }
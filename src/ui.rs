@@ -1,246 +1,1219 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
 
+use clap::ValueEnum;
 use gtk4::glib::ControlFlow;
 use gtk4::{glib, prelude::*, Application, CssProvider};
 use gtk4_layer_shell as gtk_layer_shell;
-use swayipc::{Connection, Node, NodeLayout};
 
-use crate::{cli::Args, cli::Command, sway, utils};
+use crate::{
+    cli::Action,
+    cli::Args,
+    cli::Command,
+    cli::FocusTarget,
+    cli::HintOrder,
+    grid, utils,
+    wm::{self, Event, Layout, SharedWindowManager},
+    x11,
+};
 
-// Type alias for window mapping data: (window_node, output_node, label_char)
-type WindowMapData = HashMap<i64, (Node, Node, char)>;
+// Type alias for window mapping data: con_id -> window
+type WindowMapData = HashMap<i64, wm::Window>;
 
-fn calculate_geometry(window: &Node, output: &Node, args: Arc<Args>) -> (i32, i32) {
+/// What came of showing the overlay once.
+#[derive(Debug, Default)]
+pub struct Selection {
+    /// The windows the command was run on
+    pub windows: Vec<wm::Window>,
+    /// Lines printed by the print command
+    pub output: Vec<String>,
+    /// Where the pointer command moves the cursor
+    pub pointer: Option<(i32, i32)>,
+}
+
+/// What the next keypress applies to, once a window has been picked.
+enum Pending {
+    /// The action menu is shown for this container
+    Menu(i64, gtk4::Box),
+    /// Waiting for the name of the mark to add to this container
+    Mark(i64),
+    /// Swapping two picked windows, waiting for the destination after this source
+    SwapSource(i64, bool),
+    /// Resizing this container, with an outline showing its current size
+    Resize(i64, gtk4::Box),
+    /// Placing this container on one of the grid's cells
+    Place(i64, Vec<grid::Region>),
+    /// Typing the name of a mark to run this command on its window
+    SelectMark(String, Command),
+}
+
+fn calculate_geometry(window: &wm::Window, output: &wm::Output, args: Arc<Args>) -> (i32, i32) {
     // dbg!(&window);
-    let rect = window.rect;
-    let window_rect = window.window_rect;
-    let deco_rect = window.deco_rect;
+    let (x, y) = window.hint_position;
 
-    let anchor_x = output.rect.x;
-    let anchor_y = output.rect.y;
+    let rel_x = x + args.label_margin_x.unwrap();
+    let rel_y = y + args.label_margin_y.unwrap();
 
-    let rel_x = rect.x + window_rect.x + deco_rect.x + args.label_margin_x.unwrap();
-    let rel_y = rect.y - (deco_rect.height - args.label_margin_y.unwrap())
-        + if window.layout == NodeLayout::Stacked {
-            deco_rect.y
-        } else {
-            0
-        };
+    (rel_x - output.rect.x, rel_y - output.rect.y)
+}
+
+/// Where the labels of `windows` go on `output`: over each window, or in cells of
+/// `grid_columns` columns when the compositor doesn't say where windows are.
+fn label_positions(
+    args: &Arc<Args>,
+    layout: &Layout,
+    output: &wm::Output,
+    windows: &[wm::Window],
+) -> Vec<(i32, i32)> {
+    if !layout.no_geometry {
+        return windows
+            .iter()
+            .map(|window| calculate_geometry(window, output, args.clone()))
+            .collect();
+    }
 
-    (rel_x - anchor_x, rel_y - anchor_y)
+    let columns = args.grid_columns.unwrap().max(1);
+    let rows = (windows.len() as i32 + columns - 1) / columns;
+    let region = grid::Region {
+        x: 0,
+        y: 0,
+        width: output.rect.width,
+        height: output.rect.height,
+    };
+    region
+        .cells(rows.max(1), columns)
+        .iter()
+        .take(windows.len())
+        .map(|cell| {
+            (
+                cell.x + args.label_margin_x.unwrap(),
+                cell.y + cell.height / 2,
+            )
+        })
+        .collect()
 }
 
-fn handle_keypress(
-    conn: Arc<Mutex<Connection>>,
-    key_to_con_id: &HashMap<char, i64>,
-    keyval: &str,
-    command: &Command,
-) -> bool {
+/// Returns the container whose hint is `keyval`, if any.
+fn hint_to_con_id(key_to_con_id: &HashMap<char, i64>, keyval: &str) -> Option<i64> {
     if keyval.len() == 1 {
         // we can unwrap because the keyval has one character
         let c = keyval.chars().next().unwrap();
         if c.is_alphabetic() && c.is_lowercase() {
-            if let Some(con_id) = key_to_con_id.get(&c) {
-                match &command {
-                    Command::Focus => {
-                        sway::focus(conn, *con_id);
-                        return true;
-                    }
-                    Command::Swap { focus } => {
-                        sway::swap(conn.clone(), *con_id);
+            return key_to_con_id.get(&c).copied();
+        }
+    }
+    None
+}
 
-                        if *focus {
-                            sway::focus(conn, *con_id);
+/// The container to focus for `window`, following `--focus-target`.
+fn focus_target(args: &Args, window: &wm::Window) -> i64 {
+    match args.focus_target.unwrap_or(FocusTarget::Leaf) {
+        FocusTarget::Leaf => None,
+        FocusTarget::Parent => window.parents.last(),
+        FocusTarget::Outer => window.parents.first(),
+    }
+    // windows directly on the workspace are their own parent
+    .copied()
+    .unwrap_or(window.id)
+}
+
+/// Runs `command` on `window`, where `focused` is the window that had focus before the overlay
+/// opened.
+fn run_command(
+    wm: SharedWindowManager,
+    args: &Args,
+    window: &wm::Window,
+    focused: Option<i64>,
+    command: &Command,
+    selection: &mut Selection,
+) {
+    let con_id = window.id;
+    selection.windows.push(window.clone());
+    match &command {
+        Command::Focus => {
+            wm.lock().unwrap().focus(focus_target(args, window));
+        }
+        Command::Swap { focus, .. } => {
+            // i3 focuses the overlay, so say which window to swap rather than the focused one
+            match focused {
+                Some(focused) => wm.lock().unwrap().swap_pair(focused, con_id),
+                None => wm.lock().unwrap().swap(con_id),
+            }
+
+            if *focus {
+                wm.lock().unwrap().focus(con_id);
+            }
+        }
+        Command::Bring => {
+            wm.lock().unwrap().bring(con_id);
+            wm.lock().unwrap().focus(con_id);
+        }
+        Command::Kill => {
+            wm.lock().unwrap().kill(con_id);
+        }
+        Command::Float => {
+            wm.lock().unwrap().float(con_id);
+            wm.lock().unwrap().focus(con_id);
+        }
+        Command::Fullscreen => {
+            wm.lock().unwrap().fullscreen(con_id);
+            wm.lock().unwrap().focus(con_id);
+        }
+        Command::Sticky => {
+            wm.lock().unwrap().sticky(con_id);
+        }
+        Command::Scratchpad => {
+            wm.lock().unwrap().scratchpad(con_id);
+        }
+        Command::Print { format, template } => {
+            selection
+                .output
+                .push(utils::format_window(window, *format, template.as_deref()));
+        }
+        Command::Yank { format, template } => {
+            utils::yank(&utils::format_window(window, *format, template.as_deref()));
+        }
+        Command::GotoMark => {
+            wm.lock().unwrap().focus(con_id);
+        }
+        // the key controller waits for the mark's name before marking
+        Command::Mark => {}
+        // run by `finish_selection` once the overlay has closed
+        Command::Exec { .. } => {}
+        // warped below
+        Command::Cursor => {}
+        // the key controller keeps the overlay open while resizing or placing
+        Command::Resize | Command::Place => {}
+        // doesn't select a window, see `build_pointer_ui`
+        Command::Pointer { .. } => {}
+        // handled by `main`
+        Command::Previous | Command::Daemon | Command::Trigger { .. } => {}
+    }
+
+    // The cursor has nowhere to go once the window is closed or hidden
+    let warp = match command {
+        Command::Cursor => true,
+        Command::Kill | Command::Scratchpad => false,
+        _ => args.warp_cursor.unwrap_or(false),
+    };
+    if warp {
+        warp_cursor(wm, con_id);
+    }
+}
+
+/// Move the cursor to the center of the window, unless it is no longer visible.
+fn warp_cursor(wm: SharedWindowManager, con_id: i64) {
+    let mut wm = wm.lock().unwrap();
+    // re-read the layout, since the command we just ran may have moved the window
+    let layout = wm.layout();
+    if let Some(window) = layout.window(con_id).filter(|window| window.visible) {
+        let x = window.rect.x + window.rect.width / 2;
+        let y = window.rect.y + window.rect.height / 2;
+        wm.cursor_set(x, y);
+    }
+}
+
+/// Returns the action whose menu key is `keyval`, if any.
+fn menu_action(keyval: &str) -> Option<Action> {
+    Action::value_variants()
+        .iter()
+        .find(|action| keyval == action.menu_key().to_string())
+        .copied()
+}
+
+/// Shows the action menu just below the selected window's label.
+fn show_menu(label: &gtk4::Label) -> Option<gtk4::Box> {
+    let fixed = label.parent().and_downcast::<gtk4::Fixed>()?;
+    let (x, y) = fixed.child_position(label);
+
+    let menu = gtk4::Box::new(gtk4::Orientation::Vertical, 0);
+    menu.add_css_class("menu");
+    for action in Action::value_variants() {
+        let entry = gtk4::Label::new(Some(&format!("{}  {}", action.menu_key(), action.name())));
+        entry.set_halign(gtk4::Align::Start);
+        menu.append(&entry);
+    }
+
+    fixed.put(&menu, x, y + label.height() as f64);
+    Some(menu)
+}
+
+/// Returns the mark named by `keyval`; marks are single lowercase letters, like vim's.
+fn mark_name(keyval: &str) -> Option<&str> {
+    let mut chars = keyval.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_alphabetic() && c.is_lowercase() => Some(keyval),
+        _ => None,
+    }
+}
+
+/// Closes the overlay once no key has been pressed since `last_key` for `--timeout-ms`, and
+/// with `--cancel-on-focus-loss` when it is clicked.
+fn auto_dismiss(args: &Args, windows: &[gtk4::ApplicationWindow], last_key: Rc<Cell<Instant>>) {
+    if args.cancel_on_focus_loss.unwrap_or(true) {
+        for window in windows {
+            let windows = windows.to_vec();
+            let click = gtk4::GestureClick::new();
+            click.connect_pressed(move |_, _, _, _| {
+                for w in windows.iter() {
+                    w.close();
+                }
+            });
+            window.add_controller(click);
+        }
+    }
+
+    let timeout = Duration::from_millis(args.timeout_ms.unwrap_or(0));
+    if timeout.is_zero() {
+        return;
+    }
+    let windows = windows.to_vec();
+    glib::timeout_add_local(Duration::from_millis(100), move || {
+        if !windows.iter().any(|w| w.is_visible()) {
+            return ControlFlow::Break;
+        }
+        if last_key.get().elapsed() < timeout {
+            return ControlFlow::Continue;
+        }
+        for w in windows.iter() {
+            w.close();
+        }
+        ControlFlow::Break
+    });
+}
+
+/// The letter of the window's first single-letter mark.
+fn mark_letter(window: &wm::Window) -> Option<char> {
+    window
+        .marks
+        .iter()
+        .find_map(|mark| mark_name(mark))
+        .and_then(|mark| mark.chars().next())
+}
+
+/// The label showing the window's hint, followed by its name when `listed` instead of over it.
+fn hint_label(
+    args: &Args,
+    window: &wm::Window,
+    letter: char,
+    goto_mark: bool,
+    listed: bool,
+) -> gtk4::Label {
+    let label = gtk4::Label::new(Some(""));
+    if listed {
+        let name = [&window.app_id, &window.title]
+            .into_iter()
+            .flatten()
+            .map(|name| glib::markup_escape_text(name).to_string())
+            .collect::<Vec<_>>()
+            .join(" - ");
+        label.set_markup(&format!("{}  {}", letter, name));
+        label.set_max_width_chars(40);
+        label.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+    } else if args.show_marks.unwrap_or(true) && !goto_mark && !window.marks.is_empty() {
+        label.set_markup(&format!(
+            "{}  {}",
+            letter,
+            utils::marks_to_markup(args, &window.marks)
+        ));
+    } else {
+        label.set_markup(&format!("{}", letter));
+    }
+
+    // Ensure labels are visible and properly sized on the overlay
+    label.set_halign(gtk4::Align::Center);
+    label.set_valign(gtk4::Align::Center);
+
+    if window.focused {
+        label.add_css_class("focused");
+    }
+    label
+}
+
+/// Moves the labels to where their windows are in `layout`, dropping the labels of windows that
+/// are gone and giving new windows unused hints. Outputs without an overlay are left alone.
+fn relayout(
+    args: &Arc<Args>,
+    layout: &Layout,
+    goto_mark: bool,
+    fixeds: &HashMap<String, gtk4::Fixed>,
+    key_to_con_id: &mut HashMap<char, i64>,
+    windows: &mut WindowMapData,
+    labels: &mut HashMap<i64, gtk4::Label>,
+) {
+    let letters = args.chars.clone().expect("Some characters are required");
+    let mut visible = vec![];
+
+    for output in layout.outputs.iter() {
+        let Some(fixed) = fixeds.get(&output.name) else {
+            continue;
+        };
+        let output_windows = layout.visible_windows(output);
+        let positions = label_positions(args, layout, output, &output_windows);
+        for (window, (x, y)) in output_windows.into_iter().zip(positions) {
+            if let Some(label) = labels.get(&window.id) {
+                match label.parent().and_downcast::<gtk4::Fixed>() {
+                    Some(parent) if &parent == fixed => fixed.move_(label, x as f64, y as f64),
+                    // the window moved to another output
+                    parent => {
+                        if let Some(parent) = parent {
+                            parent.remove(label);
                         }
-                        return true;
-                    }
-                    Command::Print => {
-                        println!("{}", con_id);
-                        return true;
+                        fixed.put(label, x as f64, y as f64);
                     }
                 }
+                if window.focused {
+                    label.add_css_class("focused");
+                } else {
+                    label.remove_css_class("focused");
+                }
+            } else {
+                let letter = if goto_mark {
+                    mark_letter(&window)
+                } else {
+                    letters.chars().find(|c| !key_to_con_id.contains_key(c))
+                };
+                // unmarked, or out of hints
+                let Some(letter) = letter else {
+                    continue;
+                };
+                key_to_con_id.insert(letter, window.id);
+
+                let label = hint_label(args, &window, letter, goto_mark, layout.no_geometry);
+                fixed.put(&label, x as f64, y as f64);
+                labels.insert(window.id, label);
             }
+
+            visible.push(window.id);
+            windows.insert(window.id, window);
         }
     }
-    false
+
+    labels.retain(|con_id, label| {
+        if visible.contains(con_id) {
+            return true;
+        }
+        if let Some(fixed) = label.parent().and_downcast::<gtk4::Fixed>() {
+            fixed.remove(label);
+        }
+        false
+    });
+    // marked windows on hidden workspaces can still be picked by their mark
+    key_to_con_id.retain(|_, con_id| {
+        visible.contains(con_id) || (goto_mark && layout.window(*con_id).is_some())
+    });
 }
 
-fn build_ui(app: &Application, args: Arc<Args>, conn: Arc<Mutex<Connection>>) {
-    let output_nodes = sway::get_all_output_nodes(conn.clone());
+/// Moves `outline` over the container's current geometry.
+fn place_outline(outline: &gtk4::Box, window: &wm::Window, output: &wm::Output) {
+    if let Some(fixed) = outline.parent().and_downcast::<gtk4::Fixed>() {
+        outline.set_size_request(window.rect.width, window.rect.height);
+        fixed.move_(
+            outline,
+            (window.rect.x - output.rect.x) as f64,
+            (window.rect.y - output.rect.y) as f64,
+        );
+    }
+}
 
-    // Shared state for all monitors
-    let all_key_to_con_id: Rc<RefCell<HashMap<char, i64>>> = Rc::new(RefCell::new(HashMap::new()));
-    let all_windows: Rc<RefCell<Vec<gtk4::ApplicationWindow>>> = Rc::new(RefCell::new(Vec::new()));
-    let all_windows_map: Rc<RefCell<WindowMapData>> = Rc::new(RefCell::new(HashMap::new()));
+/// Outlines the window being resized, on the same output as its label.
+fn start_resize(labels: &[gtk4::Label], window: &wm::Window) -> Option<gtk4::Box> {
+    let fixed = labels.first()?.parent().and_downcast::<gtk4::Fixed>()?;
 
-    // Get global character sequence
-    let letters = args.chars.clone().expect("Some characters are required");
-    let mut chars = letters.chars();
+    let outline = gtk4::Box::new(gtk4::Orientation::Horizontal, 0);
+    outline.add_css_class("outline");
+    fixed.put(&outline, 0.0, 0.0);
+    place_outline(&outline, window, &window.output);
+    Some(outline)
+}
 
-    // Process each output
-    for output in output_nodes {
-        let workspace = sway::get_focused_workspace(&output);
-        let windows = sway::get_all_windows(&workspace);
+/// The hints for the cells of a `grid_rows` x `grid_columns` grid.
+fn grid_hints(args: &Args) -> Vec<char> {
+    let cells = (args.grid_rows.unwrap() * args.grid_columns.unwrap()) as usize;
+    let hints: Vec<char> = args
+        .chars
+        .clone()
+        .expect("Some characters are required")
+        .chars()
+        .take(cells)
+        .collect();
+    assert!(
+        hints.len() == cells,
+        "Ran out of characters for the grid. Consider using a longer character set with \
+         --chars or a smaller grid."
+    );
+    hints
+}
 
-        // Skip empty workspaces
-        if windows.is_empty() {
-            continue;
+/// Replaces the labels on the window's output with a grid of cells to place it in.
+fn start_place(
+    args: &Args,
+    windows: &[gtk4::ApplicationWindow],
+    labels: &[gtk4::Label],
+    window: &wm::Window,
+) -> Option<Vec<grid::Region>> {
+    let fixed = labels.first()?.parent().and_downcast::<gtk4::Fixed>()?;
+
+    // Only the window's own output shows the grid
+    for w in windows.iter() {
+        if w.child() != Some(fixed.clone().upcast()) {
+            w.set_visible(false);
         }
+    }
 
-        // Create GTK window for this output
-        let window = gtk4::ApplicationWindow::new(app);
+    let output = grid::Region {
+        x: 0,
+        y: 0,
+        width: window.output.rect.width,
+        height: window.output.rect.height,
+    };
+    let cells = output.cells(args.grid_rows.unwrap(), args.grid_columns.unwrap());
+    grid::draw_grid(&fixed, &cells, &grid_hints(args));
+    Some(cells)
+}
 
-        // Configure layer shell
-        // Setting a namespace allows WM rules to target these windows.
-        gtk_layer_shell::LayerShell::init_layer_shell(&window);
-        gtk_layer_shell::LayerShell::set_namespace(&window, Some("sway-easyfocus"));
-        gtk_layer_shell::LayerShell::set_layer(&window, gtk_layer_shell::Layer::Overlay);
-        gtk_layer_shell::LayerShell::set_keyboard_mode(
-            &window,
-            gtk_layer_shell::KeyboardMode::Exclusive,
-        );
-        gtk_layer_shell::LayerShell::set_anchor(&window, gtk_layer_shell::Edge::Top, true);
-        gtk_layer_shell::LayerShell::set_anchor(&window, gtk_layer_shell::Edge::Bottom, true);
-        gtk_layer_shell::LayerShell::set_anchor(&window, gtk_layer_shell::Edge::Left, true);
-        gtk_layer_shell::LayerShell::set_anchor(&window, gtk_layer_shell::Edge::Right, true);
-
-        // Set monitor for this output
-        // This is necessary because Sway outputs (logical displays in the window manager) need to
-        // be mapped to GTK/GDK monitors (physical displays as seen by GTK) so that the overlay
-        // labels appear on the correct physical screen in a multi-monitor setup.
-        let display = gtk4::gdk::Display::default().unwrap();
-        let monitors = display.monitors();
-        for i in 0..monitors.n_items() {
-            if let Some(monitor) = monitors
-                .item(i)
-                .and_then(|obj| obj.downcast::<gtk4::gdk::Monitor>().ok())
-            {
-                let geometry = monitor.geometry();
-                if geometry.x() <= output.rect.x
-                    && output.rect.x < geometry.x() + geometry.width()
-                    && geometry.y() <= output.rect.y
-                    && output.rect.y < geometry.y() + geometry.height()
-                {
-                    gtk_layer_shell::LayerShell::set_monitor(&window, Some(&monitor));
-                    break;
+/// Whether the key being pressed is a modifier on its own, like Shift before a capital hint.
+fn is_modifier_key(controller: &gtk4::EventControllerKey, keyval: &str) -> bool {
+    let is_modifier = controller
+        .current_event()
+        .and_then(|event| event.downcast::<gtk4::gdk::KeyEvent>().ok())
+        .is_some_and(|event| event.is_modifier());
+    is_modifier
+        || matches!(
+            keyval,
+            "Shift_L"
+                | "Shift_R"
+                | "Control_L"
+                | "Control_R"
+                | "Alt_L"
+                | "Alt_R"
+                | "Meta_L"
+                | "Meta_R"
+                | "Super_L"
+                | "Super_R"
+                | "ISO_Level3_Shift"
+                | "Caps_Lock"
+        )
+}
+
+/// Picks the command for a keypress, using the modifier bindings when a modifier is held.
+fn command_for_modifiers(args: &Args, state: gtk4::gdk::ModifierType) -> Command {
+    let action = if state.contains(gtk4::gdk::ModifierType::CONTROL_MASK) {
+        args.ctrl_action
+    } else if state.contains(gtk4::gdk::ModifierType::ALT_MASK) {
+        args.alt_action
+    } else if state.contains(gtk4::gdk::ModifierType::SHIFT_MASK) {
+        args.shift_action
+    } else {
+        None
+    };
+
+    action
+        .map(Command::from)
+        .or_else(|| args.command.clone())
+        .unwrap_or(Command::Focus)
+}
+
+fn handle_keypress(
+    wm: SharedWindowManager,
+    args: &Args,
+    key_to_con_id: &HashMap<char, i64>,
+    windows: &WindowMapData,
+    keyval: &str,
+    command: &Command,
+    selection: &mut Selection,
+) -> bool {
+    if let Some(con_id) = hint_to_con_id(key_to_con_id, keyval) {
+        // the focused window is shown, so it has a label too
+        let focused = windows.values().find(|window| window.focused).map(|w| w.id);
+        run_command(wm, args, &windows[&con_id], focused, command, selection);
+        return true;
+    }
+    false
+}
+
+/// Highlights `labels` and hides everything else.
+fn highlight_labels(windows: &[gtk4::ApplicationWindow], labels: &[gtk4::Label]) {
+    // Find these labels across all windows
+    for window in windows.iter() {
+        let mut found_selected_label = false;
+        if let Some(fixed) = window
+            .child()
+            .and_then(|c| c.downcast::<gtk4::Fixed>().ok())
+        {
+            let mut child = fixed.first_child();
+            while let Some(widget) = child {
+                // Get next sibling before moving widget
+                child = widget.next_sibling();
+                if let Ok(label) = widget.downcast::<gtk4::Label>() {
+                    if labels.contains(&label) {
+                        // Update CSS class to reflect focus change
+                        label.add_css_class("focused");
+                        found_selected_label = true;
+                    } else {
+                        // Hide all other labels
+                        label.set_visible(false);
+                    }
                 }
             }
         }
+        // Hide windows that don't contain the selected label
+        if !found_selected_label {
+            window.set_visible(false);
+        }
+    }
+}
 
-        let fixed = gtk4::Fixed::new();
+/// Returns the labels shown for `con_ids`, skipping containers without one.
+fn labels_for(all_labels: &HashMap<i64, gtk4::Label>, con_ids: &[i64]) -> Vec<gtk4::Label> {
+    con_ids
+        .iter()
+        .filter_map(|con_id| all_labels.get(con_id).cloned())
+        .collect()
+}
 
-        // Create labels for windows
-        for window_node in windows.iter() {
-            let (x, y) = calculate_geometry(window_node, &output, args.clone());
-            let label = gtk4::Label::new(Some(""));
+/// Closes the overlay, after a short delay if the selection is being confirmed.
+fn close_after_selection(
+    windows: &[gtk4::ApplicationWindow],
+    labels: &[gtk4::Label],
+    show_confirmation_window: bool,
+) {
+    if show_confirmation_window {
+        highlight_labels(windows, labels);
+    } else {
+        // If no confirmation, hide all windows immediately
+        for w in windows.iter() {
+            w.set_visible(false);
+        }
+    }
+
+    // Close all windows after delay (or immediately if no confirmation)
+    let windows_to_close = windows.to_vec();
+    let delay = if show_confirmation_window { 500 } else { 0 };
+    glib::timeout_add_local(Duration::from_millis(delay), move || {
+        for w in windows_to_close.iter() {
+            w.close();
+        }
+        ControlFlow::Break
+    });
+}
+
+/// Creates a fullscreen layer-shell window on the monitor showing `output`.
+fn create_overlay_window(app: &Application, output: &wm::Output) -> gtk4::ApplicationWindow {
+    // Create GTK window for this output
+    let window = gtk4::ApplicationWindow::new(app);
+
+    // Find the monitor for this output
+    // This is necessary because Sway outputs (logical displays in the window manager) need to
+    // be mapped to GTK/GDK monitors (physical displays as seen by GTK) so that the overlay
+    // labels appear on the correct physical screen in a multi-monitor setup.
+    let display = gtk4::gdk::Display::default().unwrap();
+    let monitors = display.monitors();
+    let monitor = (0..monitors.n_items())
+        .filter_map(|i| {
+            monitors
+                .item(i)
+                .and_then(|obj| obj.downcast::<gtk4::gdk::Monitor>().ok())
+        })
+        .find(|monitor| {
+            let geometry = monitor.geometry();
+            geometry.x() <= output.rect.x
+                && output.rect.x < geometry.x() + geometry.width()
+                && geometry.y() <= output.rect.y
+                && output.rect.y < geometry.y() + geometry.height()
+        });
+
+    if x11::is_x11() {
+        // X11 has no layer shell, so the overlay is kept out of the window manager's hands
+        x11::override_redirect(window.upcast_ref(), output.rect);
+        return window;
+    }
+    if !gtk_layer_shell::is_supported() {
+        // cover the output with a borderless fullscreen window that the window manager can tell
+        // apart
+        window.set_title(Some(wm::OVERLAY_TITLE));
+        window.set_decorated(false);
+        match &monitor {
+            Some(monitor) => window.fullscreen_on_monitor(monitor),
+            None => window.fullscreen(),
+        }
+        return window;
+    }
+
+    // Configure layer shell
+    // Setting a namespace allows WM rules to target these windows.
+    gtk_layer_shell::LayerShell::init_layer_shell(&window);
+    gtk_layer_shell::LayerShell::set_namespace(&window, Some("sway-easyfocus"));
+    gtk_layer_shell::LayerShell::set_layer(&window, gtk_layer_shell::Layer::Overlay);
+    gtk_layer_shell::LayerShell::set_keyboard_mode(
+        &window,
+        gtk_layer_shell::KeyboardMode::Exclusive,
+    );
+    gtk_layer_shell::LayerShell::set_anchor(&window, gtk_layer_shell::Edge::Top, true);
+    gtk_layer_shell::LayerShell::set_anchor(&window, gtk_layer_shell::Edge::Bottom, true);
+    gtk_layer_shell::LayerShell::set_anchor(&window, gtk_layer_shell::Edge::Left, true);
+    gtk_layer_shell::LayerShell::set_anchor(&window, gtk_layer_shell::Edge::Right, true);
+    if let Some(monitor) = &monitor {
+        gtk_layer_shell::LayerShell::set_monitor(&window, Some(monitor));
+    }
+
+    window
+}
 
+/// Pairs each visible window with a hint character, in the order `--hint-order` asks for.
+fn assign_hints(
+    args: &Args,
+    visible: &[(wm::Output, Vec<wm::Window>)],
+    mru: &[i64],
+) -> HashMap<i64, char> {
+    let mut con_ids: Vec<(i64, bool)> = visible
+        .iter()
+        .flat_map(|(_, windows)| windows.iter())
+        .map(|window| (window.id, window.focused))
+        .collect();
+
+    if let Some(HintOrder::Mru) = args.hint_order {
+        // the focused window is the least likely target, so it goes last
+        con_ids.sort_by_key(|&(con_id, focused)| {
+            let rank = mru.iter().position(|&id| id == con_id);
+            (focused, rank.unwrap_or(usize::MAX))
+        });
+    }
+
+    // Get global character sequence
+    let letters = args.chars.clone().expect("Some characters are required");
+    let mut chars = letters.chars();
+    con_ids
+        .into_iter()
+        .map(|(con_id, _)| {
             let letter = chars.next().expect(
                 "Ran out of characters for highlighting windows. Consider using a longer \
                  character set with --chars or reduce the number of visible windows.",
             );
+            (con_id, letter)
+        })
+        .collect()
+}
 
-            // Store mappings
-            all_key_to_con_id
-                .borrow_mut()
-                .insert(letter, window_node.id);
-            all_windows_map.borrow_mut().insert(
-                window_node.id,
-                (window_node.clone(), output.clone(), letter),
-            );
+fn build_ui(
+    app: &Application,
+    args: Arc<Args>,
+    wm: SharedWindowManager,
+    layout: Layout,
+    layouts: mpsc::Receiver<(Event, Layout)>,
+    mru: &[i64],
+    selected: Rc<RefCell<Selection>>,
+) {
+    // Shared state for all monitors
+    let all_key_to_con_id: Rc<RefCell<HashMap<char, i64>>> = Rc::new(RefCell::new(HashMap::new()));
+    let all_windows: Rc<RefCell<Vec<gtk4::ApplicationWindow>>> = Rc::new(RefCell::new(Vec::new()));
+    let all_windows_map: Rc<RefCell<WindowMapData>> = Rc::new(RefCell::new(HashMap::new()));
+    let all_labels: Rc<RefCell<HashMap<i64, gtk4::Label>>> = Rc::new(RefCell::new(HashMap::new()));
+    // Each output's labels, by output name
+    let all_fixed: Rc<RefCell<HashMap<String, gtk4::Fixed>>> =
+        Rc::new(RefCell::new(HashMap::new()));
+    // Containers toggled on in multi-select mode, in the order they were picked
+    let multi_selection: Rc<RefCell<Vec<i64>>> = Rc::new(RefCell::new(Vec::new()));
+    // When the last key was pressed, for `--timeout-ms`
+    let last_key = Rc::new(Cell::new(Instant::now()));
+    // A picked container waiting for another key
+    let pending: Rc<RefCell<Option<Pending>>> = Rc::new(RefCell::new(None));
 
-            label.set_markup(&format!("{}", letter));
+    let goto_mark = matches!(args.command, Some(Command::GotoMark));
+    let focused = layout
+        .windows
+        .iter()
+        .find(|window| window.focused)
+        .map(|window| window.id);
 
-            // Ensure labels are visible and properly sized on the overlay
-            label.set_halign(gtk4::Align::Center);
-            label.set_valign(gtk4::Align::Center);
+    // Marked windows on hidden workspaces can be selected without a label
+    let select_by_mark = args.select_by_mark.unwrap_or(false);
+    let all_marks: Rc<RefCell<HashMap<String, i64>>> = Rc::new(RefCell::new(HashMap::new()));
+    if goto_mark || select_by_mark {
+        for window in layout.marked_windows() {
+            if let Some(letter) = mark_letter(&window) {
+                if goto_mark {
+                    all_key_to_con_id.borrow_mut().insert(letter, window.id);
+                }
+            }
+            for mark in window.marks.iter() {
+                all_marks.borrow_mut().insert(mark.clone(), window.id);
+            }
+            all_windows_map.borrow_mut().insert(window.id, window);
+        }
+    }
 
-            fixed.put(&label, x as f64, y as f64);
+    // Skip empty workspaces. Fullscreen overlays are all opened on the focused output by the
+    // window manager, so only that one gets labels. Marks can reach windows on hidden
+    // workspaces, so the focused output keeps its overlay to type them into
+    let focused_output = layout.focused_output().map(|output| output.name.clone());
+    let typing_marks = (goto_mark || select_by_mark) && !all_marks.borrow().is_empty();
+    let visible: Vec<(wm::Output, Vec<wm::Window>)> = layout
+        .outputs
+        .iter()
+        .filter(|output| {
+            gtk_layer_shell::is_supported()
+                || x11::is_x11()
+                || Some(&output.name) == focused_output.as_ref()
+        })
+        .map(|output| (output.clone(), layout.visible_windows(output)))
+        .filter(|(output, windows)| {
+            !windows.is_empty() || (typing_marks && Some(&output.name) == focused_output.as_ref())
+        })
+        .collect();
+    // marks stand in for hints when going to one
+    let hints = if goto_mark {
+        HashMap::new()
+    } else {
+        assign_hints(&args, &visible, mru)
+    };
 
-            if window_node.focused {
-                label.add_css_class("focused");
-            }
+    // Process each output
+    for (output, windows) in visible {
+        let window = create_overlay_window(app, &output);
+
+        let fixed = gtk4::Fixed::new();
+
+        // Create labels for windows
+        let positions = label_positions(&args, &layout, &output, &windows);
+        for (wm_window, &(x, y)) in windows.iter().zip(positions.iter()) {
+            let letter = if goto_mark {
+                // The window's mark is its hint, and unmarked windows get no label
+                match mark_letter(wm_window) {
+                    Some(letter) => letter,
+                    None => continue,
+                }
+            } else {
+                hints[&wm_window.id]
+            };
+
+            // Store mappings
+            all_key_to_con_id.borrow_mut().insert(letter, wm_window.id);
+            all_windows_map
+                .borrow_mut()
+                .insert(wm_window.id, wm_window.clone());
+
+            let label = hint_label(&args, wm_window, letter, goto_mark, layout.no_geometry);
+            fixed.put(&label, x as f64, y as f64);
+            all_labels.borrow_mut().insert(wm_window.id, label);
         }
+        all_fixed
+            .borrow_mut()
+            .insert(output.name.clone(), fixed.clone());
 
         // Set up key handler - use global key map for both single and multi-monitor
         let key_map = all_key_to_con_id.clone();
 
         let all_windows_clone = all_windows.clone();
         let args_clone = args.clone();
-        let conn_clone = conn.clone();
+        let wm_clone = wm.clone();
         let all_windows_map_clone = all_windows_map.clone();
+        let selected_clone = selected.clone();
+        let all_labels_clone = all_labels.clone();
+        let multi_selection_clone = multi_selection.clone();
+        let pending_clone = pending.clone();
+        let all_marks_clone = all_marks.clone();
+        let last_key_clone = last_key.clone();
 
         // GTK 4 uses EventControllerKey for keyboard input
         let key_controller = gtk4::EventControllerKey::new();
-        key_controller.connect_key_pressed(move |_, keyval, _keycode, _state| {
-            let keyval_name = keyval.name();
+        key_controller.connect_key_pressed(move |controller, keyval, _keycode, state| {
+            last_key_clone.set(Instant::now());
+            // Shift turns hints into capitals, but we still want to match them
+            let keyval_name = keyval.to_lower().name();
             if let Some(keyval_str) = keyval_name {
                 let keyval_str = keyval_str.as_str();
+                // Modifiers only change the next key, in any state
+                if is_modifier_key(controller, keyval_str) {
+                    return glib::Propagation::Proceed;
+                }
+                let command = command_for_modifiers(&args_clone, state);
+                let show_confirmation = args_clone.show_confirmation.unwrap_or(true);
 
-                let window_focused = handle_keypress(
-                    conn_clone.clone(),
-                    &key_map.borrow(),
-                    keyval_str,
-                    &args_clone.command.unwrap_or(Command::Focus),
-                );
-
-                if window_focused {
-                    let c = keyval_str.chars().next().unwrap();
-                    let show_confirmation = args_clone.show_confirmation.unwrap_or(true);
-
-                    // Find and update the selected label, hide all other labels
-                    if show_confirmation {
-                        if let Some(con_id) = key_map.borrow().get(&c) {
-                            if let Some((_, _, _)) = all_windows_map_clone.borrow().get(con_id) {
-                                // Find the label for this character across all windows
-                                for window in all_windows_clone.borrow().iter() {
-                                    let mut found_selected_label = false;
-                                    if let Some(fixed) = window
-                                        .child()
-                                        .and_then(|c| c.downcast::<gtk4::Fixed>().ok())
-                                    {
-                                        let mut child = fixed.first_child();
-                                        while let Some(widget) = child {
-                                            // Get next sibling before moving widget
-                                            child = widget.next_sibling();
-                                            if let Ok(label) = widget.downcast::<gtk4::Label>() {
-                                                if label.text() == c.to_string() {
-                                                    // Update CSS class to reflect focus change
-                                                    label.add_css_class("focused");
-                                                    found_selected_label = true;
-                                                } else {
-                                                    // Hide all other labels
-                                                    label.set_visible(false);
-                                                }
-                                            }
-                                        }
+                // A window was already picked, so this key chooses what to do with it
+                if let Some(pending) = pending_clone.take() {
+                    match pending {
+                        Pending::Menu(con_id, menu) => {
+                            menu.set_visible(false);
+                            match menu_action(keyval_str) {
+                                Some(Action::Mark) => {
+                                    pending_clone.replace(Some(Pending::Mark(con_id)));
+                                    return glib::Propagation::Stop;
+                                }
+                                Some(Action::Place) => {
+                                    if let Some(cells) = start_place(
+                                        &args_clone,
+                                        &all_windows_clone.borrow(),
+                                        &labels_for(&all_labels_clone.borrow(), &[con_id]),
+                                        &all_windows_map_clone.borrow()[&con_id],
+                                    ) {
+                                        pending_clone.replace(Some(Pending::Place(con_id, cells)));
+                                        return glib::Propagation::Stop;
                                     }
-                                    // Hide windows that don't contain the selected label
-                                    if !found_selected_label {
-                                        window.set_visible(false);
+                                }
+                                Some(Action::Resize) => {
+                                    if let Some(outline) = start_resize(
+                                        &labels_for(&all_labels_clone.borrow(), &[con_id]),
+                                        &all_windows_map_clone.borrow()[&con_id],
+                                    ) {
+                                        pending_clone
+                                            .replace(Some(Pending::Resize(con_id, outline)));
+                                        return glib::Propagation::Stop;
                                     }
                                 }
+                                Some(action) => {
+                                    let window = &all_windows_map_clone.borrow()[&con_id];
+                                    run_command(
+                                        wm_clone.clone(),
+                                        &args_clone,
+                                        window,
+                                        focused,
+                                        &action.into(),
+                                        &mut selected_clone.borrow_mut(),
+                                    );
+                                    close_after_selection(
+                                        &all_windows_clone.borrow(),
+                                        &labels_for(&all_labels_clone.borrow(), &[con_id]),
+                                        show_confirmation,
+                                    );
+                                    return glib::Propagation::Stop;
+                                }
+                                None => {}
                             }
                         }
-                    } else {
-                        // If no confirmation, hide all windows immediately
-                        for w in all_windows_clone.borrow().iter() {
-                            w.set_visible(false);
+                        Pending::Resize(con_id, outline) => {
+                            let resize_keys = args_clone.resize_keys.clone().unwrap();
+                            let steps = [
+                                (wm::Resize::Shrink, wm::Dimension::Width),
+                                (wm::Resize::Grow, wm::Dimension::Height),
+                                (wm::Resize::Shrink, wm::Dimension::Height),
+                                (wm::Resize::Grow, wm::Dimension::Width),
+                            ];
+                            if let Some((change, dimension)) = resize_keys
+                                .chars()
+                                .zip(steps)
+                                .find(|(key, _)| keyval_str == key.to_string())
+                                .map(|(_, step)| step)
+                            {
+                                wm_clone.lock().unwrap().resize(
+                                    con_id,
+                                    change,
+                                    dimension,
+                                    args_clone.resize_step.unwrap(),
+                                );
+
+                                // Follow the container's new geometry
+                                let layout = wm_clone.lock().unwrap().layout();
+                                if let Some(window) = layout.window(con_id) {
+                                    place_outline(
+                                        &outline,
+                                        window,
+                                        &all_windows_map_clone.borrow()[&con_id].output,
+                                    );
+                                }
+                                pending_clone.replace(Some(Pending::Resize(con_id, outline)));
+                                return glib::Propagation::Stop;
+                            }
+
+                            // Enter or Escape finish resizing, other keys are ignored
+                            if !matches!(keyval_str, "Return" | "KP_Enter" | "Escape") {
+                                pending_clone.replace(Some(Pending::Resize(con_id, outline)));
+                                return glib::Propagation::Stop;
+                            }
+                            let window = all_windows_map_clone.borrow()[&con_id].clone();
+                            selected_clone.borrow_mut().windows.push(window);
+                        }
+                        Pending::Place(con_id, cells) => {
+                            if let Some(cell) = grid_hints(&args_clone)
+                                .iter()
+                                .position(|c| keyval_str == c.to_string())
+                                .and_then(|i| cells.get(i))
+                            {
+                                let output = &all_windows_map_clone.borrow()[&con_id].output;
+                                wm_clone.lock().unwrap().place(
+                                    con_id,
+                                    wm::Rect {
+                                        x: output.rect.x + cell.x,
+                                        y: output.rect.y + cell.y,
+                                        width: cell.width,
+                                        height: cell.height,
+                                    },
+                                );
+                                wm_clone.lock().unwrap().focus(con_id);
+                                let window = all_windows_map_clone.borrow()[&con_id].clone();
+                                selected_clone.borrow_mut().windows.push(window);
+                                for w in all_windows_clone.borrow().iter() {
+                                    w.close();
+                                }
+                                return glib::Propagation::Stop;
+                            }
+                        }
+                        Pending::SelectMark(mut name, command) => {
+                            match keyval_str {
+                                "BackSpace" => {
+                                    name.pop();
+                                }
+                                "Return" | "KP_Enter" => {}
+                                "Escape" => name.clear(),
+                                _ => match keyval.to_unicode() {
+                                    Some(c) if !c.is_control() => name.push(c),
+                                    // keys that type nothing leave the name alone
+                                    _ => {
+                                        pending_clone
+                                            .replace(Some(Pending::SelectMark(name, command)));
+                                        return glib::Propagation::Stop;
+                                    }
+                                },
+                            }
+
+                            // Select the window once the name can't match any other mark
+                            let marks = all_marks_clone.borrow();
+                            let matching = marks.keys().filter(|m| m.starts_with(&name)).count();
+                            let con_id = marks.get(&name).copied();
+                            drop(marks);
+                            match con_id {
+                                Some(con_id)
+                                    if matching == 1
+                                        || keyval_str == "Return"
+                                        || keyval_str == "KP_Enter" =>
+                                {
+                                    let window = &all_windows_map_clone.borrow()[&con_id];
+                                    run_command(
+                                        wm_clone.clone(),
+                                        &args_clone,
+                                        window,
+                                        focused,
+                                        &command,
+                                        &mut selected_clone.borrow_mut(),
+                                    );
+                                    close_after_selection(
+                                        &all_windows_clone.borrow(),
+                                        &labels_for(&all_labels_clone.borrow(), &[con_id]),
+                                        show_confirmation,
+                                    );
+                                    return glib::Propagation::Stop;
+                                }
+                                _ if matching > 0 && !name.is_empty() => {
+                                    pending_clone.replace(Some(Pending::SelectMark(name, command)));
+                                    return glib::Propagation::Stop;
+                                }
+                                _ => {}
+                            }
                         }
+                        Pending::SwapSource(source, focus) => {
+                            if let Some(con_id) = hint_to_con_id(&key_map.borrow(), keyval_str)
+                                .filter(|con_id| *con_id != source)
+                            {
+                                wm_clone.lock().unwrap().swap_pair(source, con_id);
+                                let windows = all_windows_map_clone.borrow();
+                                selected_clone
+                                    .borrow_mut()
+                                    .windows
+                                    .extend([windows[&source].clone(), windows[&con_id].clone()]);
+                                drop(windows);
+                                if focus {
+                                    wm_clone.lock().unwrap().focus(con_id);
+                                }
+                                if args_clone.warp_cursor.unwrap_or(false) {
+                                    warp_cursor(wm_clone.clone(), con_id);
+                                }
+                                close_after_selection(
+                                    &all_windows_clone.borrow(),
+                                    &labels_for(&all_labels_clone.borrow(), &[source, con_id]),
+                                    show_confirmation,
+                                );
+                                return glib::Propagation::Stop;
+                            }
+                        }
+                        Pending::Mark(con_id) => {
+                            if let Some(name) = mark_name(keyval_str) {
+                                wm_clone.lock().unwrap().mark(con_id, name);
+                                let window = all_windows_map_clone.borrow()[&con_id].clone();
+                                selected_clone.borrow_mut().windows.push(window);
+                                close_after_selection(
+                                    &all_windows_clone.borrow(),
+                                    &labels_for(&all_labels_clone.borrow(), &[con_id]),
+                                    show_confirmation,
+                                );
+                                return glib::Propagation::Stop;
+                            }
+                        }
+                    }
+
+                    // Close windows on escape or invalid key
+                    for w in all_windows_clone.borrow().iter() {
+                        w.close();
+                    }
+                    return glib::Propagation::Stop;
+                }
+
+                // An apostrophe starts typing the name of a mark, like vim's jumps
+                if select_by_mark && keyval_str == "apostrophe" {
+                    pending_clone.replace(Some(Pending::SelectMark(String::new(), command)));
+                    return glib::Propagation::Stop;
+                }
+
+                // The first of two swapped windows stays selected until the second is picked
+                if let Command::Swap {
+                    focus,
+                    pick_two: true,
+                } = command
+                {
+                    if let Some(con_id) = hint_to_con_id(&key_map.borrow(), keyval_str) {
+                        labels_for(&all_labels_clone.borrow(), &[con_id])
+                            .iter()
+                            .for_each(|l| l.add_css_class("selected"));
+                        pending_clone.replace(Some(Pending::SwapSource(con_id, focus)));
+                        return glib::Propagation::Stop;
+                    }
+                }
+
+                // Placing shows a grid over the window's output after the hint
+                if let Command::Place = command {
+                    if let Some(con_id) = hint_to_con_id(&key_map.borrow(), keyval_str) {
+                        if let Some(cells) = start_place(
+                            &args_clone,
+                            &all_windows_clone.borrow(),
+                            &labels_for(&all_labels_clone.borrow(), &[con_id]),
+                            &all_windows_map_clone.borrow()[&con_id],
+                        ) {
+                            pending_clone.replace(Some(Pending::Place(con_id, cells)));
+                            return glib::Propagation::Stop;
+                        }
+                    }
+                }
+
+                // Resizing keeps the overlay open until Enter or Escape
+                if let Command::Resize = command {
+                    if let Some(con_id) = hint_to_con_id(&key_map.borrow(), keyval_str) {
+                        if let Some(outline) = start_resize(
+                            &labels_for(&all_labels_clone.borrow(), &[con_id]),
+                            &all_windows_map_clone.borrow()[&con_id],
+                        ) {
+                            highlight_labels(
+                                &all_windows_clone.borrow(),
+                                &labels_for(&all_labels_clone.borrow(), &[con_id]),
+                            );
+                            pending_clone.replace(Some(Pending::Resize(con_id, outline)));
+                            return glib::Propagation::Stop;
+                        }
+                    }
+                }
+
+                // Marking waits for the mark's name after the hint
+                if let Command::Mark = command {
+                    if let Some(con_id) = hint_to_con_id(&key_map.borrow(), keyval_str) {
+                        highlight_labels(
+                            &all_windows_clone.borrow(),
+                            &labels_for(&all_labels_clone.borrow(), &[con_id]),
+                        );
+                        pending_clone.replace(Some(Pending::Mark(con_id)));
+                        return glib::Propagation::Stop;
                     }
+                }
 
-                    // Close all windows after delay (or immediately if no confirmation)
-                    let windows_to_close = all_windows_clone.borrow().clone();
-                    let delay = if show_confirmation { 500 } else { 0 };
-                    glib::timeout_add_local(Duration::from_millis(delay), move || {
-                        for w in windows_to_close.iter() {
-                            w.close();
+                // Modifier bindings still act immediately when the menu is enabled
+                let modifiers = gtk4::gdk::ModifierType::SHIFT_MASK
+                    | gtk4::gdk::ModifierType::CONTROL_MASK
+                    | gtk4::gdk::ModifierType::ALT_MASK;
+                if args_clone.menu.unwrap_or(false)
+                    && !args_clone.multi.unwrap_or(false)
+                    && !state.intersects(modifiers)
+                {
+                    if let Some(con_id) = hint_to_con_id(&key_map.borrow(), keyval_str) {
+                        let labels = labels_for(&all_labels_clone.borrow(), &[con_id]);
+                        if let Some(menu) = labels.first().and_then(show_menu) {
+                            highlight_labels(&all_windows_clone.borrow(), &labels);
+                            pending_clone.replace(Some(Pending::Menu(con_id, menu)));
+                            return glib::Propagation::Stop;
+                        }
+                    }
+                }
+
+                if args_clone.multi.unwrap_or(false) {
+                    // Hints toggle windows in and out of the selection
+                    if let Some(con_id) = hint_to_con_id(&key_map.borrow(), keyval_str) {
+                        let mut selection = multi_selection_clone.borrow_mut();
+                        let labels = labels_for(&all_labels_clone.borrow(), &[con_id]);
+                        if let Some(i) = selection.iter().position(|id| *id == con_id) {
+                            selection.remove(i);
+                            labels.iter().for_each(|l| l.remove_css_class("selected"));
+                        } else {
+                            selection.push(con_id);
+                            labels.iter().for_each(|l| l.add_css_class("selected"));
+                        }
+                        return glib::Propagation::Stop;
+                    }
+
+                    // Enter applies the command to the whole selection
+                    if keyval_str == "Return" || keyval_str == "KP_Enter" {
+                        let selection = multi_selection_clone.borrow();
+                        for con_id in selection.iter() {
+                            let window = &all_windows_map_clone.borrow()[con_id];
+                            // each yank would replace the last, so they're copied together below
+                            if let Command::Yank { .. } = command {
+                                selected_clone.borrow_mut().windows.push(window.clone());
+                            } else {
+                                run_command(
+                                    wm_clone.clone(),
+                                    &args_clone,
+                                    window,
+                                    focused,
+                                    &command,
+                                    &mut selected_clone.borrow_mut(),
+                                );
+                            }
                         }
-                        ControlFlow::Break
-                    });
+                        if let Command::Yank { format, template } = &command {
+                            let text = selected_clone
+                                .borrow()
+                                .windows
+                                .iter()
+                                .map(|window| {
+                                    utils::format_window(window, *format, template.as_deref())
+                                })
+                                .collect::<Vec<_>>()
+                                .join("\n");
+                            utils::yank(&text);
+                        }
+                        close_after_selection(
+                            &all_windows_clone.borrow(),
+                            &labels_for(&all_labels_clone.borrow(), &selection),
+                            show_confirmation && !selection.is_empty(),
+                        );
+                        return glib::Propagation::Stop;
+                    }
+
+                    // Close windows on escape or invalid key
+                    for w in all_windows_clone.borrow().iter() {
+                        w.close();
+                    }
+                    return glib::Propagation::Stop;
+                }
+
+                let window_focused = handle_keypress(
+                    wm_clone.clone(),
+                    &args_clone,
+                    &key_map.borrow(),
+                    &all_windows_map_clone.borrow(),
+                    keyval_str,
+                    &command,
+                    &mut selected_clone.borrow_mut(),
+                );
+
+                if window_focused {
+                    let c = keyval_str.chars().next().unwrap();
+
+                    if let Some(con_id) = key_map.borrow().get(&c) {
+                        close_after_selection(
+                            &all_windows_clone.borrow(),
+                            &labels_for(&all_labels_clone.borrow(), &[*con_id]),
+                            show_confirmation,
+                        );
+                    }
 
                     glib::Propagation::Stop
                 } else {
@@ -264,9 +1237,119 @@ fn build_ui(app: &Application, args: Arc<Args>, conn: Arc<Mutex<Connection>>) {
     for window in all_windows.borrow().iter() {
         window.present();
     }
+
+    auto_dismiss(&args, &all_windows.borrow(), last_key);
+
+    // Keep the labels in step with the layout while the overlay is open
+    let workspace = layout.focused_workspace().map(|workspace| workspace.id);
+    glib::timeout_add_local(Duration::from_millis(50), move || {
+        if !all_windows.borrow().iter().any(|w| w.is_visible()) {
+            return ControlFlow::Break;
+        }
+        // only the latest layout matters
+        if let Some((_, layout)) = layouts.try_iter().last() {
+            // the user has moved on, unless it was our own command that switched workspace
+            if args.cancel_on_focus_loss.unwrap_or(true)
+                && layout.focused_workspace().map(|workspace| workspace.id) != workspace
+                && selected.borrow().windows.is_empty()
+            {
+                for w in all_windows.borrow().iter() {
+                    w.close();
+                }
+                return ControlFlow::Break;
+            }
+            // labels hidden or replaced on purpose, e.g. by the place grid, stay that way
+            if pending.borrow().is_some() || !selected.borrow().windows.is_empty() {
+                return ControlFlow::Continue;
+            }
+            relayout(
+                &args,
+                &layout,
+                goto_mark,
+                &all_fixed.borrow(),
+                &mut all_key_to_con_id.borrow_mut(),
+                &mut all_windows_map.borrow_mut(),
+                &mut all_labels.borrow_mut(),
+            );
+        }
+        ControlFlow::Continue
+    });
+}
+
+/// Builds the keynav-style grid over the focused output used by the pointer command.
+fn build_pointer_ui(
+    app: &Application,
+    args: Arc<Args>,
+    layout: Layout,
+    selected: Rc<RefCell<Selection>>,
+) {
+    let output = layout
+        .focused_output()
+        .expect("could not find focused output")
+        .clone();
+    let window = create_overlay_window(app, &output);
+    let fixed = gtk4::Fixed::new();
+
+    let rows = args.grid_rows.unwrap();
+    let columns = args.grid_columns.unwrap();
+    let hints = grid_hints(&args);
+
+    // The part of the output we've narrowed down to so far
+    let region = Rc::new(RefCell::new(grid::Region {
+        x: 0,
+        y: 0,
+        width: output.rect.width,
+        height: output.rect.height,
+    }));
+    grid::draw_grid(&fixed, &region.borrow().cells(rows, columns), &hints);
+
+    let window_clone = window.clone();
+    let last_key = Rc::new(Cell::new(Instant::now()));
+    let last_key_clone = last_key.clone();
+    let fixed_clone = fixed.clone();
+    let key_controller = gtk4::EventControllerKey::new();
+    key_controller.connect_key_pressed(move |controller, keyval, _keycode, _state| {
+        last_key_clone.set(Instant::now());
+        let keyval_name = keyval.name();
+        if let Some(keyval_str) = keyval_name {
+            let keyval_str = keyval_str.as_str();
+            if is_modifier_key(controller, keyval_str) {
+                return glib::Propagation::Proceed;
+            }
+
+            let mut finished = matches!(keyval_str, "Return" | "KP_Enter" | "space");
+            if let Some(i) = hints.iter().position(|c| keyval_str == c.to_string()) {
+                let cell = region.borrow().cells(rows, columns)[i];
+                region.replace(cell);
+
+                // Stop once the cells would be smaller than a pixel
+                if cell.width < columns || cell.height < rows {
+                    finished = true;
+                } else {
+                    grid::draw_grid(&fixed_clone, &cell.cells(rows, columns), &hints);
+                    return glib::Propagation::Stop;
+                }
+            }
+
+            // The cursor is moved by `finish_selection` once the overlay is out of the way
+            if finished {
+                let (x, y) = region.borrow().center();
+                selected.borrow_mut().pointer = Some((output.rect.x + x, output.rect.y + y));
+            }
+            window_clone.close();
+            glib::Propagation::Stop
+        } else {
+            glib::Propagation::Proceed
+        }
+    });
+
+    window.add_controller(key_controller);
+    window.set_child(Some(&fixed));
+    window.present();
+    auto_dismiss(&args, &[window], last_key);
 }
 
-fn load_css(args: Arc<Args>) {
+pub fn load_css(args: Arc<Args>) {
     let provider = CssProvider::new();
     provider.load_from_data(&utils::args_to_css(&args));
 
@@ -278,7 +1361,74 @@ fn load_css(args: Arc<Args>) {
     );
 }
 
-pub fn run_ui(conn: Arc<Mutex<Connection>>, args: Arc<Args>) {
+/// Shows the overlay once, following `layouts` while it is open, with `mru` listing windows most
+/// recently used first, calling `on_close` with the selection after it has closed.
+pub fn show_overlay(
+    app: &Application,
+    args: Arc<Args>,
+    wm: SharedWindowManager,
+    layout: Layout,
+    layouts: mpsc::Receiver<(Event, Layout)>,
+    mru: &[i64],
+    on_close: Box<dyn FnOnce(Selection)>,
+) {
+    let selected = Rc::new(RefCell::new(Selection::default()));
+    if let Some(Command::Pointer { .. }) = args.command {
+        build_pointer_ui(app, args, layout, selected.clone());
+    } else {
+        build_ui(app, args, wm, layout, layouts, mru, selected.clone());
+    }
+
+    // The overlay is gone once all of its windows are
+    let on_close = RefCell::new(Some(on_close));
+    let handler: Rc<RefCell<Option<glib::SignalHandlerId>>> = Rc::new(RefCell::new(None));
+    let handler_clone = handler.clone();
+    let finish = move |app: &Application| {
+        if let Some(on_close) = on_close.take() {
+            if let Some(handler) = handler_clone.take() {
+                app.disconnect(handler);
+            }
+            on_close(selected.take());
+        }
+    };
+
+    if app.windows().is_empty() {
+        // nothing to show, e.g. every workspace is empty
+        finish(app);
+    } else {
+        handler.replace(Some(app.connect_window_removed(move |app, _| {
+            if app.windows().is_empty() {
+                finish(app);
+            }
+        })));
+    }
+}
+
+/// Runs what has to wait for the overlay to close, returning the lines to print.
+pub fn finish_selection(wm: SharedWindowManager, args: &Args, selection: Selection) -> Vec<String> {
+    // Make sure the compositor has taken the overlay down before acting behind it
+    if let Some(display) = gtk4::gdk::Display::default() {
+        display.sync();
+    }
+
+    if let Some(Command::Exec { command }) = &args.command {
+        for window in selection.windows.iter() {
+            utils::exec(command, window);
+        }
+    }
+    if let (Some(Command::Pointer { click, button }), Some((x, y))) =
+        (&args.command, selection.pointer)
+    {
+        wm.lock().unwrap().cursor_set(x, y);
+        if *click {
+            wm.lock().unwrap().click(button);
+        }
+    }
+
+    selection.output
+}
+
+pub fn run_ui(wm: SharedWindowManager, args: Arc<Args>) {
     let app = Application::builder()
         .application_id("com.github.edzdez.sway-easyfocus")
         .build();
@@ -287,9 +1437,168 @@ pub fn run_ui(conn: Arc<Mutex<Connection>>, args: Arc<Args>) {
     app.connect_startup(move |_| load_css(args_clone.clone()));
 
     app.connect_activate(move |app| {
-        build_ui(app, args.clone(), conn.clone());
+        let args = args.clone();
+        let wm = wm.clone();
+        let layout = wm.lock().unwrap().layout();
+        let layouts = wm.lock().unwrap().subscribe();
+        show_overlay(
+            app,
+            args.clone(),
+            wm.clone(),
+            layout,
+            layouts,
+            &[],
+            Box::new(move |selection| {
+                for line in finish_selection(wm, &args, selection) {
+                    println!("{}", line);
+                }
+            }),
+        );
     });
 
     let empty: Vec<String> = vec![];
     app.run_with_args(&empty);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wm::{mock, Rect};
+
+    fn rect(x: i32, y: i32) -> Rect {
+        Rect {
+            x,
+            y,
+            width: 100,
+            height: 100,
+        }
+    }
+
+    fn args(chars: &str, hint_order: HintOrder) -> Arc<Args> {
+        Arc::new(Args {
+            chars: Some(chars.to_string()),
+            hint_order: Some(hint_order),
+            ..Args::default()
+        })
+    }
+
+    #[test]
+    fn hints_go_by_position() {
+        let windows = vec![
+            mock::window(1, rect(0, 0)),
+            mock::window(2, rect(100, 0)),
+            mock::window(3, rect(200, 0)),
+        ];
+        let hints = assign_hints(
+            &args("abc", HintOrder::Position),
+            &[(mock::output(), windows)],
+            &[3],
+        );
+        assert_eq!(hints, HashMap::from([(1, 'a'), (2, 'b'), (3, 'c')]));
+    }
+
+    #[test]
+    fn hints_go_by_mru_with_the_focused_window_last() {
+        let mut focused = mock::window(1, rect(0, 0));
+        focused.focused = true;
+        let windows = vec![
+            focused,
+            mock::window(2, rect(100, 0)),
+            mock::window(3, rect(200, 0)),
+        ];
+        let hints = assign_hints(
+            &args("abc", HintOrder::Mru),
+            &[(mock::output(), windows)],
+            &[1, 3],
+        );
+        assert_eq!(hints, HashMap::from([(3, 'a'), (2, 'b'), (1, 'c')]));
+    }
+
+    #[test]
+    fn labels_go_over_their_windows() {
+        let args = Arc::new(Args::default());
+        let windows = vec![mock::window(1, rect(100, 50))];
+        let layout = mock::layout(windows.clone());
+        let mut output = mock::output();
+        output.rect.x = 50;
+
+        let (margin_x, margin_y) = (args.label_margin_x.unwrap(), args.label_margin_y.unwrap());
+        assert_eq!(
+            label_positions(&args, &layout, &output, &windows),
+            [(50 + margin_x, 50 + margin_y)]
+        );
+    }
+
+    #[test]
+    fn labels_are_listed_without_geometry() {
+        let args = Arc::new(Args {
+            grid_columns: Some(3),
+            ..Args::default()
+        });
+        let windows: Vec<_> = (1..=4)
+            .map(|id| mock::window(id, Rect::default()))
+            .collect();
+        let mut layout = mock::layout(windows.clone());
+        layout.no_geometry = true;
+
+        // two rows of three cells over the 1920x1080 output
+        let margin_x = args.label_margin_x.unwrap();
+        assert_eq!(
+            label_positions(&args, &layout, &mock::output(), &windows),
+            [
+                (margin_x, 270),
+                (640 + margin_x, 270),
+                (1280 + margin_x, 270),
+                (margin_x, 810),
+            ]
+        );
+    }
+
+    #[test]
+    fn focus_target_follows_parents() {
+        let mut window = mock::window(1, rect(0, 0));
+        let focus = |target, window: &wm::Window| {
+            let args = Args {
+                focus_target: Some(target),
+                ..Args::default()
+            };
+            focus_target(&args, window)
+        };
+        assert_eq!(focus(FocusTarget::Parent, &window), 1);
+        assert_eq!(focus(FocusTarget::Outer, &window), 1);
+
+        window.parents = vec![10, 11];
+        assert_eq!(focus(FocusTarget::Leaf, &window), 1);
+        assert_eq!(focus(FocusTarget::Parent, &window), 11);
+        assert_eq!(focus(FocusTarget::Outer, &window), 10);
+    }
+
+    #[test]
+    fn swaps_name_the_previously_focused_window() {
+        let wm = mock::MockWindowManager::new(Layout::default());
+        let window = mock::window(2, rect(0, 0));
+        let swap = Command::Swap {
+            focus: false,
+            pick_two: false,
+        };
+        let mut selection = Selection::default();
+        run_command(
+            wm.clone(),
+            &Args::default(),
+            &window,
+            Some(1),
+            &swap,
+            &mut selection,
+        );
+        run_command(
+            wm.clone(),
+            &Args::default(),
+            &window,
+            None,
+            &swap,
+            &mut selection,
+        );
+
+        assert_eq!(wm.lock().unwrap().calls, ["swap 1 2", "swap 2"]);
+    }
+}
@@ -1,4 +1,10 @@
-use crate::cli::Args;
+use crate::{
+    cli::{Args, PrintFormat},
+    wm::{Rect, Window},
+};
+use gtk4::{gdk, gio, glib, prelude::*};
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::str::FromStr;
 
 // stolen from https://rust-lang-nursery.github.io/rust-cookbook/text/string_parsing.html
@@ -39,6 +45,11 @@ pub fn args_to_css(args: &Args) -> String {
     let focused_fg = Rgb::from_str(args.focused_text_color.as_ref().unwrap())
         .expect("invalid color for focused_text_color");
 
+    let selected_bg = Rgb::from_str(args.selected_background_color.as_ref().unwrap())
+        .expect("invalid color for selected_background_color");
+    let selected_fg = Rgb::from_str(args.selected_text_color.as_ref().unwrap())
+        .expect("invalid color for selected_text_color");
+
     format!(
         r#"
         window {{
@@ -58,6 +69,28 @@ pub fn args_to_css(args: &Args) -> String {
             background: rgba({}, {}, {}, {});
             color: rgb({}, {}, {});
         }}
+
+        .selected {{
+            background: rgba({}, {}, {}, {});
+            color: rgb({}, {}, {});
+        }}
+
+        .cell {{
+            border: 1px solid rgb({}, {}, {});
+        }}
+
+        .outline {{
+            border: 2px solid rgb({}, {}, {});
+        }}
+
+        .menu {{
+            background: rgba({}, {}, {}, {});
+            border: 1px solid rgb({}, {}, {});
+        }}
+
+        .menu label {{
+            background: none;
+        }}
         "#,
         window_bg.r,
         window_bg.g,
@@ -81,6 +114,205 @@ pub fn args_to_css(args: &Args) -> String {
         args.focused_background_opacity.unwrap(),
         focused_fg.r,
         focused_fg.g,
-        focused_fg.b
+        focused_fg.b,
+        selected_bg.r,
+        selected_bg.g,
+        selected_bg.b,
+        args.selected_background_opacity.unwrap(),
+        selected_fg.r,
+        selected_fg.g,
+        selected_fg.b,
+        label_fg.r,
+        label_fg.g,
+        label_fg.b,
+        focused_bg.r,
+        focused_bg.g,
+        focused_bg.b,
+        label_bg.r,
+        label_bg.g,
+        label_bg.b,
+        args.label_background_opacity.unwrap(),
+        label_fg.r,
+        label_fg.g,
+        label_fg.b
     )
 }
+
+/// Pango markup for a window's marks, shown after its hint as `[mark]`.
+pub fn marks_to_markup(args: &Args, marks: &[String]) -> String {
+    let color = Rgb::from_str(args.mark_text_color.as_ref().unwrap())
+        .expect("invalid color for mark_text_color");
+
+    let marks = marks
+        .iter()
+        .map(|mark| format!("[{}]", glib::markup_escape_text(mark)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        r##"<span foreground="#{:02x}{:02x}{:02x}">{}</span>"##,
+        color.r, color.g, color.b, marks
+    )
+}
+
+/// Formats a rect as slurp-style `x,y wxh` geometry.
+pub fn rect_to_geometry(rect: &Rect) -> String {
+    format!("{},{} {}x{}", rect.x, rect.y, rect.width, rect.height)
+}
+
+/// The selected window's details as `(key, value)` pairs, shared by `print` and `exec`.
+pub fn window_fields(window: &Window) -> Vec<(&'static str, String)> {
+    vec![
+        ("id", window.id.to_string()),
+        ("app_id", window.app_id.clone().unwrap_or_default()),
+        ("class", window.class.clone().unwrap_or_default()),
+        ("title", window.title.clone().unwrap_or_default()),
+        (
+            "pid",
+            window.pid.map(|pid| pid.to_string()).unwrap_or_default(),
+        ),
+        ("workspace", window.workspace.clone()),
+        ("output", window.output.name.clone()),
+        ("rect", rect_to_geometry(&window.rect)),
+        ("floating", window.floating.to_string()),
+        ("marks", window.marks.join(",")),
+    ]
+}
+
+/// Environment variables describing `window`, passed to commands run by `exec`.
+pub fn window_env(window: &Window) -> Vec<(String, String)> {
+    window_fields(window)
+        .into_iter()
+        .map(|(key, value)| {
+            let key = if key == "id" { "con_id" } else { key };
+            (format!("EASYFOCUS_{}", key.to_uppercase()), value)
+        })
+        .collect()
+}
+
+/// The window's details as a JSON object, as printed by `--format json`.
+pub fn window_json(window: &Window) -> serde_json::Value {
+    serde_json::json!({
+        "id": window.id,
+        "app_id": window.app_id,
+        "class": window.class,
+        "title": window.title,
+        "pid": window.pid,
+        "workspace": window.workspace,
+        "output": window.output.name,
+        "rect": window.rect,
+        "floating": window.floating,
+        "marks": window.marks,
+    })
+}
+
+pub fn format_window(window: &Window, format: PrintFormat, template: Option<&str>) -> String {
+    match format {
+        PrintFormat::Id => window.id.to_string(),
+        PrintFormat::Title => window.title.clone().unwrap_or_default(),
+        PrintFormat::AppId => window.app_id.clone().unwrap_or_default(),
+        PrintFormat::Json => window_json(window).to_string(),
+        PrintFormat::Tsv => window_fields(window)
+            .into_iter()
+            // keep every field on one line and in its own column
+            .map(|(_, value)| value.replace(['\t', '\n'], " "))
+            .collect::<Vec<_>>()
+            .join("\t"),
+        PrintFormat::Slurp => rect_to_geometry(&window.rect),
+        PrintFormat::Template => fill_template(template.unwrap_or("{id}"), &window_fields(window)),
+    }
+}
+
+/// Replaces each `{field}` in `template` with its value, in one pass so values that look like
+/// placeholders are left alone. Unknown placeholders are kept as they are.
+fn fill_template(template: &str, fields: &[(&str, String)]) -> String {
+    let mut filled = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let value = rest.find('}').and_then(|end| {
+            let (_, value) = fields.iter().find(|(key, _)| *key == &rest[1..end])?;
+            Some((value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                filled.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// Puts `text` on the clipboard.
+///
+/// Wayland clipboards are served by the client that owns them, so this keeps the application
+/// running (like `wl-copy`) until something else is copied.
+pub fn yank(text: &str) {
+    let clipboard = gdk::Display::default().unwrap().clipboard();
+    clipboard.set_text(text);
+
+    if let Some(app) = gio::Application::default() {
+        let guard = RefCell::new(Some(app.hold()));
+        let handler: Rc<RefCell<Option<glib::SignalHandlerId>>> = Rc::new(RefCell::new(None));
+        let handler_clone = handler.clone();
+        handler.replace(Some(clipboard.connect_changed(move |clipboard| {
+            if !clipboard.is_local() {
+                guard.take();
+                // later yanks hold the application themselves
+                if let Some(handler) = handler_clone.take() {
+                    clipboard.disconnect(handler);
+                }
+            }
+        })));
+    }
+}
+
+/// Starts `command` without waiting for it: a single argument is run as a shell command, several
+/// are run as a program and its arguments, keeping their quoting.
+pub fn exec(command: &[String], window: &Window) {
+    let mut process = match command {
+        [] => return,
+        [script] => {
+            let mut process = std::process::Command::new("sh");
+            process.arg("-c").arg(script);
+            process
+        }
+        [program, args @ ..] => {
+            let mut process = std::process::Command::new(program);
+            process.args(args);
+            process
+        }
+    };
+    match process.envs(window_env(window)).spawn() {
+        // reap it once it exits, so the daemon doesn't collect zombies
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(err) => eprintln!("failed to run {:?}: {}", command, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_template_replaces_fields() {
+        let fields = [("id", "7".to_string()), ("title", "vim".to_string())];
+        assert_eq!(fill_template("{id}: {title}", &fields), "7: vim");
+        assert_eq!(fill_template("{nope} {id", &fields), "{nope} {id");
+    }
+
+    #[test]
+    fn fill_template_leaves_substituted_values_alone() {
+        let fields = [("title", "{pid}".to_string()), ("pid", "42".to_string())];
+        assert_eq!(fill_template("{title} {pid}", &fields), "{pid} 42");
+    }
+}
@@ -0,0 +1,672 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{mpsc, Arc, Condvar, Mutex},
+};
+
+use wayland_client::{
+    delegate_noop, event_created_child,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{wl_callback, wl_output, wl_registry, wl_seat},
+    Connection, Dispatch, Proxy, QueueHandle, WEnum,
+};
+use wayland_protocols::xdg::xdg_output::zv1::client::{
+    zxdg_output_manager_v1::ZxdgOutputManagerV1, zxdg_output_v1,
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+use crate::wm::{self, Dimension, Layout, Resize, WindowManager};
+
+#[derive(Default)]
+struct OutputInfo {
+    name: String,
+    x: i32,
+    y: i32,
+    /// Size of the current mode, in pixels
+    mode: (i32, i32),
+    scale: i32,
+    /// Whether the output is turned on its side
+    rotated: bool,
+    /// Where the output is in the layout, from xdg-output, which knows about fractional scales
+    logical: Option<wm::Rect>,
+}
+
+#[derive(Default)]
+struct Toplevel {
+    title: Option<String>,
+    app_id: Option<String>,
+    /// Protocol IDs of the outputs the toplevel is on
+    outputs: Vec<u32>,
+    activated: bool,
+    fullscreen: bool,
+}
+
+#[derive(Default)]
+struct State {
+    /// By the protocol ID of their `wl_output`
+    outputs: BTreeMap<u32, OutputInfo>,
+    /// By the protocol ID of their handle, which is the order they were opened in
+    toplevels: BTreeMap<u32, Toplevel>,
+    handles: HashMap<u32, ZwlrForeignToplevelHandleV1>,
+    /// The output holding the last activated toplevel, which stays put while the overlay has
+    /// the keyboard
+    focused_output: Option<u32>,
+    done_callbacks: HashSet<u32>,
+    subscribers: Vec<mpsc::Sender<(wm::Event, Layout)>>,
+    disconnected: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    synced: Condvar,
+}
+
+/// Handles the compositor's events on the thread that reads them.
+struct Handler {
+    shared: Arc<Shared>,
+    xdg_output_manager: Option<ZxdgOutputManagerV1>,
+    /// Protocol IDs of the outputs, by the name of their global
+    output_globals: HashMap<u32, u32>,
+}
+
+/// Lists and activates toplevels with the `zwlr_foreign_toplevel_manager_v1` protocol, for
+/// wlroots compositors without an IPC of their own. The protocol has no geometry, so the
+/// overlay lists windows instead of labelling them in place.
+pub struct Wlroots {
+    connection: Connection,
+    queue: QueueHandle<Handler>,
+    shared: Arc<Shared>,
+    seat: Option<wl_seat::WlSeat>,
+    next_callback: u32,
+}
+
+impl OutputInfo {
+    fn rect(&self) -> wm::Rect {
+        if let Some(rect) = self.logical {
+            return rect;
+        }
+        // without xdg-output, the scale can only be a whole number
+        let (width, height) = if self.rotated {
+            (self.mode.1, self.mode.0)
+        } else {
+            self.mode
+        };
+        let scale = self.scale.max(1);
+        wm::Rect {
+            x: self.x,
+            y: self.y,
+            width: width / scale,
+            height: height / scale,
+        }
+    }
+}
+
+impl Handler {
+    fn bind_output(
+        &mut self,
+        registry: &wl_registry::WlRegistry,
+        name: u32,
+        version: u32,
+        queue: &QueueHandle<Self>,
+    ) {
+        let output: wl_output::WlOutput = registry.bind(name, version.min(4), queue, ());
+        let id = output.id().protocol_id();
+        if let Some(manager) = &self.xdg_output_manager {
+            manager.get_xdg_output(&output, queue, id);
+        }
+        self.output_globals.insert(name, id);
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .outputs
+            .insert(id, OutputInfo::default());
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for Handler {
+    fn event(
+        handler: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        queue: &QueueHandle<Self>,
+    ) {
+        match event {
+            // outputs plugged in later
+            wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } if interface == "wl_output" => handler.bind_output(registry, name, version, queue),
+            wl_registry::Event::GlobalRemove { name } => {
+                if let Some(id) = handler.output_globals.remove(&name) {
+                    handler.shared.state.lock().unwrap().outputs.remove(&id);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, ()> for Handler {
+    fn event(
+        handler: &mut Self,
+        output: &wl_output::WlOutput,
+        event: wl_output::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let mut state = handler.shared.state.lock().unwrap();
+        let Some(info) = state.outputs.get_mut(&output.id().protocol_id()) else {
+            return;
+        };
+        match event {
+            wl_output::Event::Geometry {
+                x, y, transform, ..
+            } => {
+                (info.x, info.y) = (x, y);
+                info.rotated = matches!(
+                    transform,
+                    WEnum::Value(
+                        wl_output::Transform::_90
+                            | wl_output::Transform::_270
+                            | wl_output::Transform::Flipped90
+                            | wl_output::Transform::Flipped270
+                    )
+                );
+            }
+            // only the current mode matters
+            wl_output::Event::Mode {
+                flags: WEnum::Value(flags),
+                width,
+                height,
+                ..
+            } if flags.contains(wl_output::Mode::Current) => info.mode = (width, height),
+            wl_output::Event::Scale { factor } => info.scale = factor,
+            wl_output::Event::Name { name } => info.name = name,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<zxdg_output_v1::ZxdgOutputV1, u32> for Handler {
+    fn event(
+        handler: &mut Self,
+        _: &zxdg_output_v1::ZxdgOutputV1,
+        event: zxdg_output_v1::Event,
+        output: &u32,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let mut state = handler.shared.state.lock().unwrap();
+        let Some(info) = state.outputs.get_mut(output) else {
+            return;
+        };
+        match event {
+            zxdg_output_v1::Event::LogicalPosition { x, y } => {
+                let logical = info.logical.get_or_insert_with(wm::Rect::default);
+                (logical.x, logical.y) = (x, y);
+            }
+            zxdg_output_v1::Event::LogicalSize { width, height } => {
+                let logical = info.logical.get_or_insert_with(wm::Rect::default);
+                (logical.width, logical.height) = (width, height);
+            }
+            zxdg_output_v1::Event::Name { name } => info.name = name,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_callback::WlCallback, u32> for Handler {
+    fn event(
+        handler: &mut Self,
+        _: &wl_callback::WlCallback,
+        event: wl_callback::Event,
+        callback: &u32,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_callback::Event::Done { .. } = event {
+            let mut state = handler.shared.state.lock().unwrap();
+            state.done_callbacks.insert(*callback);
+            handler.shared.synced.notify_all();
+        }
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for Handler {
+    fn event(
+        handler: &mut Self,
+        _: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event {
+            let mut state = handler.shared.state.lock().unwrap();
+            let id = toplevel.id().protocol_id();
+            state.toplevels.insert(id, Toplevel::default());
+            state.handles.insert(id, toplevel);
+        }
+    }
+
+    event_created_child!(Handler, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for Handler {
+    fn event(
+        handler: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use zwlr_foreign_toplevel_handle_v1::{Event, State as ToplevelState};
+
+        let mut state = handler.shared.state.lock().unwrap();
+        let id = handle.id().protocol_id();
+        let Some(toplevel) = state.toplevels.get_mut(&id) else {
+            return;
+        };
+        let mut change = None;
+        match event {
+            Event::Title { title } => toplevel.title = Some(title),
+            Event::AppId { app_id } => toplevel.app_id = Some(app_id),
+            Event::OutputEnter { output } => toplevel.outputs.push(output.id().protocol_id()),
+            Event::OutputLeave { output } => {
+                let output = output.id().protocol_id();
+                toplevel.outputs.retain(|&id| id != output);
+            }
+            Event::State { state: states } => {
+                let states: Vec<u32> = states
+                    .chunks_exact(4)
+                    .map(|state| u32::from_ne_bytes(state.try_into().unwrap()))
+                    .collect();
+                let activated = states.contains(&(ToplevelState::Activated as u32));
+                if activated && !toplevel.activated {
+                    change = Some(wm::Event::Focus(id as i64));
+                }
+                toplevel.activated = activated;
+                toplevel.fullscreen = states.contains(&(ToplevelState::Fullscreen as u32));
+            }
+            // sent after a batch of changes
+            Event::Done => {
+                if let (true, Some(&output)) = (toplevel.activated, toplevel.outputs.first()) {
+                    state.focused_output = Some(output);
+                }
+                change.get_or_insert(wm::Event::Change);
+            }
+            Event::Closed => {
+                state.toplevels.remove(&id);
+                state.handles.remove(&id);
+                handle.destroy();
+                change = Some(wm::Event::Close(id as i64));
+            }
+            _ => {}
+        }
+
+        if let Some(event) = change {
+            let layout = to_layout(&state);
+            state
+                .subscribers
+                .retain(|sender| sender.send((event, layout.clone())).is_ok());
+        }
+    }
+}
+
+delegate_noop!(Handler: ignore wl_seat::WlSeat);
+delegate_noop!(Handler: ZxdgOutputManagerV1);
+
+fn to_layout(state: &State) -> Layout {
+    let mut layout = Layout {
+        no_geometry: true,
+        ..Layout::default()
+    };
+    let focused_output = state
+        .focused_output
+        .filter(|id| state.outputs.contains_key(id))
+        .or(state.outputs.keys().next().copied());
+
+    // there are no workspaces, so each output stands in for the one it shows
+    for (&id, info) in state.outputs.iter() {
+        let output = wm::Output {
+            name: info.name.clone(),
+            rect: info.rect(),
+        };
+        layout.workspaces.push(wm::Workspace {
+            id: id as i64,
+            name: output.name.clone(),
+            output: output.name.clone(),
+            visible: true,
+            focused: Some(id) == focused_output,
+        });
+
+        for (&toplevel_id, toplevel) in state.toplevels.iter() {
+            // toplevels on no output, e.g. minimized ones, are listed on the focused output
+            let on_output = match toplevel.outputs.first() {
+                Some(&output) => output == id,
+                None => Some(id) == focused_output,
+            };
+            if !on_output {
+                continue;
+            }
+            layout.windows.push(wm::Window {
+                id: toplevel_id as i64,
+                app_id: toplevel.app_id.clone(),
+                class: None,
+                title: toplevel.title.clone(),
+                pid: None,
+                rect: wm::Rect::default(),
+                hint_position: (output.rect.x, output.rect.y),
+                workspace: output.name.clone(),
+                output: output.clone(),
+                floating: false,
+                focused: toplevel.activated,
+                visible: true,
+                marks: vec![],
+                parents: vec![],
+            });
+        }
+        layout.outputs.push(output);
+    }
+    layout
+}
+
+impl Wlroots {
+    pub fn connect() -> Self {
+        let connection = Connection::connect_to_env().expect("failed to connect to the compositor");
+        let (globals, mut queue) = registry_queue_init::<Handler>(&connection)
+            .expect("failed to list the compositor's globals");
+        let handle = queue.handle();
+
+        globals
+            .bind::<ZwlrForeignToplevelManagerV1, _, _>(&handle, 1..=3, ())
+            .expect("the compositor doesn't support zwlr_foreign_toplevel_manager_v1");
+        let seat = globals.bind(&handle, 1..=1, ()).ok();
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            synced: Condvar::new(),
+        });
+        let mut handler = Handler {
+            shared: shared.clone(),
+            xdg_output_manager: globals.bind(&handle, 1..=3, ()).ok(),
+            output_globals: HashMap::new(),
+        };
+        for global in globals.contents().clone_list() {
+            if global.interface == "wl_output" {
+                handler.bind_output(globals.registry(), global.name, global.version, &handle);
+            }
+        }
+        // get the state of everything that was just bound before anyone asks for it
+        queue
+            .roundtrip(&mut handler)
+            .expect("lost the connection to the compositor");
+
+        std::thread::spawn(move || {
+            while queue.blocking_dispatch(&mut handler).is_ok() {}
+            handler.shared.state.lock().unwrap().disconnected = true;
+            handler.shared.synced.notify_all();
+        });
+
+        Wlroots {
+            connection,
+            queue: handle,
+            shared,
+            seat,
+            next_callback: 0,
+        }
+    }
+
+    /// Waits until the compositor has handled every request sent so far.
+    fn roundtrip(&mut self) {
+        let callback = self.next_callback;
+        self.next_callback += 1;
+        self.connection.display().sync(&self.queue, callback);
+        let _ = self.connection.flush();
+
+        let mut state = self.shared.state.lock().unwrap();
+        while !state.done_callbacks.remove(&callback) {
+            assert!(!state.disconnected, "lost the connection to the compositor");
+            state = self.shared.synced.wait(state).unwrap();
+        }
+    }
+
+    /// Sends a request to a toplevel and waits for the compositor to handle it.
+    fn request(&mut self, id: i64, request: impl FnOnce(&ZwlrForeignToplevelHandleV1)) {
+        let handle = self
+            .shared
+            .state
+            .lock()
+            .unwrap()
+            .handles
+            .get(&(id as u32))
+            .cloned();
+        if let Some(handle) = handle {
+            request(&handle);
+            self.roundtrip();
+        }
+    }
+
+    fn unsupported(&self, what: &str) {
+        eprintln!("foreign toplevel management has no way to {}", what);
+    }
+}
+
+impl WindowManager for Wlroots {
+    fn layout(&mut self) -> Layout {
+        self.roundtrip();
+        to_layout(&self.shared.state.lock().unwrap())
+    }
+
+    fn subscribe(&self) -> mpsc::Receiver<(wm::Event, Layout)> {
+        let (sender, receiver) = mpsc::channel();
+        self.shared.state.lock().unwrap().subscribers.push(sender);
+        receiver
+    }
+
+    fn focus(&mut self, id: i64) {
+        match self.seat.clone() {
+            Some(seat) => self.request(id, |handle| handle.activate(&seat)),
+            None => eprintln!("the compositor has no seat to focus windows with"),
+        }
+    }
+
+    fn swap(&mut self, _id: i64) {
+        self.unsupported("swap windows");
+    }
+
+    fn swap_pair(&mut self, _source: i64, _id: i64) {
+        self.unsupported("swap windows");
+    }
+
+    fn bring(&mut self, _id: i64) {
+        self.unsupported("move windows between workspaces");
+    }
+
+    fn kill(&mut self, id: i64) {
+        self.request(id, |handle| handle.close());
+    }
+
+    fn float(&mut self, _id: i64) {
+        self.unsupported("float windows");
+    }
+
+    fn fullscreen(&mut self, id: i64) {
+        let fullscreen = {
+            let state = self.shared.state.lock().unwrap();
+            let toplevel = state.toplevels.get(&(id as u32));
+            toplevel.is_some_and(|toplevel| toplevel.fullscreen)
+        };
+        self.request(id, |handle| {
+            if handle.version() < 2 {
+                eprintln!("foreign toplevel management has no way to make windows fullscreen");
+            } else if fullscreen {
+                handle.unset_fullscreen();
+            } else {
+                // on whichever output the compositor picks
+                handle.set_fullscreen(None);
+            }
+        });
+    }
+
+    fn sticky(&mut self, _id: i64) {
+        self.unsupported("make windows sticky");
+    }
+
+    fn scratchpad(&mut self, _id: i64) {
+        self.unsupported("hide windows in a scratchpad");
+    }
+
+    fn resize(&mut self, _id: i64, _change: Resize, _dimension: Dimension, _amount: i32) {
+        self.unsupported("resize windows");
+    }
+
+    fn place(&mut self, _id: i64, _rect: wm::Rect) {
+        self.unsupported("place windows");
+    }
+
+    fn mark(&mut self, _id: i64, _name: &str) {
+        self.unsupported("mark windows");
+    }
+
+    fn cursor_set(&mut self, _x: i32, _y: i32) {
+        self.unsupported("move the cursor");
+    }
+
+    fn click(&mut self, _button: &str) {
+        self.unsupported("click");
+    }
+
+    fn run_command(&mut self, _command: &str) {
+        self.unsupported("run commands");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toplevel(title: &str, outputs: Vec<u32>, activated: bool) -> Toplevel {
+        Toplevel {
+            title: Some(title.to_string()),
+            outputs,
+            activated,
+            ..Toplevel::default()
+        }
+    }
+
+    #[test]
+    fn outputs_are_sized_in_layout_coordinates() {
+        let mut state = State::default();
+        // a fractional scale, which only xdg-output gets right
+        state.outputs.insert(
+            3,
+            OutputInfo {
+                name: "DP-1".to_string(),
+                mode: (2880, 1620),
+                scale: 2,
+                logical: Some(wm::Rect {
+                    x: 0,
+                    y: 0,
+                    width: 1920,
+                    height: 1080,
+                }),
+                ..OutputInfo::default()
+            },
+        );
+        // turned on its side, without xdg-output
+        state.outputs.insert(
+            4,
+            OutputInfo {
+                name: "HDMI-A-1".to_string(),
+                x: 1920,
+                mode: (3840, 2160),
+                scale: 2,
+                rotated: true,
+                ..OutputInfo::default()
+            },
+        );
+
+        let outputs: Vec<_> = to_layout(&state)
+            .outputs
+            .into_iter()
+            .map(|output| (output.name, output.rect))
+            .collect();
+        assert_eq!(
+            outputs,
+            [
+                (
+                    "DP-1".to_string(),
+                    wm::Rect {
+                        x: 0,
+                        y: 0,
+                        width: 1920,
+                        height: 1080
+                    }
+                ),
+                (
+                    "HDMI-A-1".to_string(),
+                    wm::Rect {
+                        x: 1920,
+                        y: 0,
+                        width: 1080,
+                        height: 1920
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn toplevels_are_listed_on_their_output() {
+        let mut state = State::default();
+        for (id, name) in [(3, "DP-1"), (4, "HDMI-A-1")] {
+            let info = OutputInfo {
+                name: name.to_string(),
+                ..OutputInfo::default()
+            };
+            state.outputs.insert(id, info);
+        }
+        state
+            .toplevels
+            .insert(10, toplevel("editor", vec![3], false));
+        state
+            .toplevels
+            .insert(11, toplevel("browser", vec![4], true));
+        // minimized
+        state.toplevels.insert(12, toplevel("chat", vec![], false));
+        state.focused_output = Some(4);
+
+        let layout = to_layout(&state);
+        let windows: Vec<_> = layout
+            .windows
+            .iter()
+            .map(|window| (window.id, window.output.name.as_str(), window.focused))
+            .collect();
+        assert_eq!(
+            windows,
+            [
+                (10, "DP-1", false),
+                (11, "HDMI-A-1", true),
+                (12, "HDMI-A-1", false)
+            ]
+        );
+        assert!(layout.no_geometry);
+        assert_eq!(
+            layout
+                .focused_workspace()
+                .map(|workspace| workspace.name.as_str()),
+            Some("HDMI-A-1")
+        );
+    }
+}
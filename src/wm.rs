@@ -0,0 +1,397 @@
+use std::sync::{mpsc, Arc, Mutex};
+
+use serde::Serialize;
+
+use crate::{cli::Backend, hyprland::Hyprland, niri::Niri, sway::Sway, wlroots::Wlroots};
+
+/// Title of the overlay's windows, for compositors that manage them like any other window.
+pub const OVERLAY_TITLE: &str = "sway-easyfocus";
+
+/// A rectangle in global coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub name: String,
+    pub rect: Rect,
+}
+
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub id: i64,
+    pub name: String,
+    /// Name of the output the workspace is on
+    pub output: String,
+    /// Whether it is the workspace shown on its output
+    pub visible: bool,
+    /// Whether it is the workspace with the keyboard focus
+    pub focused: bool,
+}
+
+/// A window together with the workspace and output it is on.
+#[derive(Debug, Clone)]
+pub struct Window {
+    pub id: i64,
+    pub app_id: Option<String>,
+    /// The X11 class, for Xwayland and X11 windows
+    pub class: Option<String>,
+    pub title: Option<String>,
+    pub pid: Option<i32>,
+    /// Geometry of the whole container, including decorations
+    pub rect: Rect,
+    /// Where the top-left corner of the hint goes
+    pub hint_position: (i32, i32),
+    pub workspace: String,
+    pub output: Output,
+    pub floating: bool,
+    pub focused: bool,
+    /// Whether the window can be seen, e.g. it isn't behind another tab
+    pub visible: bool,
+    pub marks: Vec<String>,
+    /// IDs of the containers holding the window, from the workspace's child down to its parent
+    pub parents: Vec<i64>,
+}
+
+/// The outputs, workspaces and windows at one moment.
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    pub outputs: Vec<Output>,
+    pub workspaces: Vec<Workspace>,
+    /// Every window on every workspace, output by output and in hint order within a workspace
+    pub windows: Vec<Window>,
+    /// Whether the compositor doesn't say where windows are, so the overlay lists them instead
+    /// of labelling them in place
+    pub no_geometry: bool,
+}
+
+impl Layout {
+    pub fn window(&self, id: i64) -> Option<&Window> {
+        self.windows.iter().find(|window| window.id == id)
+    }
+
+    /// Windows on the workspace shown on `output`.
+    pub fn visible_windows(&self, output: &Output) -> Vec<Window> {
+        self.windows
+            .iter()
+            .filter(|window| window.output.name == output.name)
+            .filter(|window| {
+                self.workspaces.iter().any(|workspace| {
+                    workspace.visible
+                        && workspace.output == output.name
+                        && workspace.name == window.workspace
+                })
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn focused_workspace(&self) -> Option<&Workspace> {
+        self.workspaces.iter().find(|workspace| workspace.focused)
+    }
+
+    pub fn focused_output(&self) -> Option<&Output> {
+        let workspace = self.focused_workspace()?;
+        self.outputs
+            .iter()
+            .find(|output| output.name == workspace.output)
+    }
+
+    /// Every window with a mark, on any workspace.
+    pub fn marked_windows(&self) -> Vec<Window> {
+        self.windows
+            .iter()
+            .filter(|window| !window.marks.is_empty())
+            .cloned()
+            .collect()
+    }
+}
+
+/// What changed, as reported by `WindowManager::subscribe`.
+#[derive(Debug, Clone, Copy)]
+pub enum Event {
+    /// The window with this ID was focused
+    Focus(i64),
+    /// The window with this ID was closed
+    Close(i64),
+    /// Anything else about the windows or workspaces
+    Change,
+}
+
+/// Which way `WindowManager::resize` changes a window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resize {
+    Grow,
+    Shrink,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dimension {
+    Width,
+    Height,
+}
+
+/// What the overlay needs from a compositor.
+pub trait WindowManager: Send {
+    fn layout(&mut self) -> Layout;
+
+    /// Sends every change along with the layout after it, until the receiver is dropped.
+    fn subscribe(&self) -> mpsc::Receiver<(Event, Layout)>;
+
+    fn focus(&mut self, id: i64);
+    /// Swaps the focused window with this one.
+    fn swap(&mut self, id: i64);
+    fn swap_pair(&mut self, source: i64, id: i64);
+    /// Moves the window to the current workspace.
+    fn bring(&mut self, id: i64);
+    fn kill(&mut self, id: i64);
+    fn float(&mut self, id: i64);
+    fn fullscreen(&mut self, id: i64);
+    fn sticky(&mut self, id: i64);
+    fn scratchpad(&mut self, id: i64);
+    /// Grows or shrinks the window's width or height by `amount` px.
+    fn resize(&mut self, id: i64, change: Resize, dimension: Dimension, amount: i32);
+    /// Floats the window and fits it to `rect`.
+    fn place(&mut self, id: i64, rect: Rect);
+    fn mark(&mut self, id: i64, name: &str);
+    fn cursor_set(&mut self, x: i32, y: i32);
+    /// Clicks `button`, named like sway's `button1`.
+    fn click(&mut self, button: &str);
+    /// Runs a command in the compositor's own language.
+    fn run_command(&mut self, command: &str);
+}
+
+pub type SharedWindowManager = Arc<Mutex<dyn WindowManager>>;
+
+/// Connects to the compositor named by `backend`, moving the cursor of `seat` where there are
+/// several.
+pub fn connect(backend: Backend, seat: &str) -> SharedWindowManager {
+    let is_set = |name| std::env::var_os(name).is_some();
+    let backend = match backend {
+        Backend::Auto if is_set("HYPRLAND_INSTANCE_SIGNATURE") => Backend::Hyprland,
+        Backend::Auto if is_set("NIRI_SOCKET") => Backend::Niri,
+        // sway sets I3SOCK too
+        Backend::Auto if is_set("SWAYSOCK") => Backend::Sway,
+        Backend::Auto if is_set("I3SOCK") || !is_set("WAYLAND_DISPLAY") => Backend::I3,
+        Backend::Auto => Backend::Wlroots,
+        backend => backend,
+    };
+
+    match backend {
+        Backend::Hyprland => Arc::new(Mutex::new(Hyprland::connect())),
+        Backend::I3 => Arc::new(Mutex::new(Sway::connect_i3())),
+        Backend::Niri => Arc::new(Mutex::new(Niri::connect())),
+        Backend::Wlroots => Arc::new(Mutex::new(Wlroots::connect())),
+        _ => Arc::new(Mutex::new(Sway::connect(seat))),
+    }
+}
+
+/// A compositor for tests, serving a fixed layout and recording what it is asked to do.
+#[cfg(test)]
+pub mod mock {
+    use super::*;
+    use std::{
+        io::Write,
+        os::unix::net::{UnixListener, UnixStream},
+    };
+
+    #[derive(Default)]
+    pub struct MockWindowManager {
+        pub layout: Layout,
+        pub calls: Vec<String>,
+    }
+
+    impl MockWindowManager {
+        pub fn new(layout: Layout) -> Arc<Mutex<Self>> {
+            Arc::new(Mutex::new(MockWindowManager {
+                layout,
+                calls: vec![],
+            }))
+        }
+
+        fn record(&mut self, call: String) {
+            self.calls.push(call);
+        }
+    }
+
+    impl WindowManager for MockWindowManager {
+        fn layout(&mut self) -> Layout {
+            self.layout.clone()
+        }
+
+        fn subscribe(&self) -> mpsc::Receiver<(Event, Layout)> {
+            // nothing ever changes
+            mpsc::channel().1
+        }
+
+        fn focus(&mut self, id: i64) {
+            self.record(format!("focus {}", id));
+        }
+
+        fn swap(&mut self, id: i64) {
+            self.record(format!("swap {}", id));
+        }
+
+        fn swap_pair(&mut self, source: i64, id: i64) {
+            self.record(format!("swap {} {}", source, id));
+        }
+
+        fn bring(&mut self, id: i64) {
+            self.record(format!("bring {}", id));
+        }
+
+        fn kill(&mut self, id: i64) {
+            self.record(format!("kill {}", id));
+        }
+
+        fn float(&mut self, id: i64) {
+            self.record(format!("float {}", id));
+        }
+
+        fn fullscreen(&mut self, id: i64) {
+            self.record(format!("fullscreen {}", id));
+        }
+
+        fn sticky(&mut self, id: i64) {
+            self.record(format!("sticky {}", id));
+        }
+
+        fn scratchpad(&mut self, id: i64) {
+            self.record(format!("scratchpad {}", id));
+        }
+
+        fn resize(&mut self, id: i64, change: Resize, dimension: Dimension, amount: i32) {
+            self.record(format!(
+                "resize {} {:?} {:?} {}",
+                id, change, dimension, amount
+            ));
+        }
+
+        fn place(&mut self, id: i64, rect: Rect) {
+            self.record(format!("place {} {:?}", id, rect));
+        }
+
+        fn mark(&mut self, id: i64, name: &str) {
+            self.record(format!("mark {} {}", id, name));
+        }
+
+        fn cursor_set(&mut self, x: i32, y: i32) {
+            self.record(format!("cursor {} {}", x, y));
+        }
+
+        fn click(&mut self, button: &str) {
+            self.record(format!("click {}", button));
+        }
+
+        fn run_command(&mut self, command: &str) {
+            self.record(format!("run {}", command));
+        }
+    }
+
+    /// A tiled window on workspace "1" of output "DP-1".
+    pub fn window(id: i64, rect: Rect) -> Window {
+        Window {
+            id,
+            app_id: Some(format!("app{}", id)),
+            class: None,
+            title: Some(format!("window {}", id)),
+            pid: None,
+            rect,
+            hint_position: (rect.x, rect.y),
+            workspace: "1".to_string(),
+            output: output(),
+            floating: false,
+            focused: false,
+            visible: true,
+            marks: vec![],
+            parents: vec![],
+        }
+    }
+
+    pub fn output() -> Output {
+        Output {
+            name: "DP-1".to_string(),
+            rect: Rect {
+                x: 0,
+                y: 0,
+                width: 1920,
+                height: 1080,
+            },
+        }
+    }
+
+    /// One output showing workspace "1" with `windows` on it.
+    pub fn layout(windows: Vec<Window>) -> Layout {
+        Layout {
+            outputs: vec![output()],
+            workspaces: vec![Workspace {
+                id: 1,
+                name: "1".to_string(),
+                output: "DP-1".to_string(),
+                visible: true,
+                focused: true,
+            }],
+            windows,
+            no_geometry: false,
+        }
+    }
+
+    /// Serves a compositor's IPC socket at `path`, taking each connection's request with `read`
+    /// and answering it with `reply`. Returns the requests received so far.
+    pub fn fake_socket(
+        path: &std::path::Path,
+        read: fn(&mut UnixStream) -> String,
+        reply: impl Fn(&str) -> String + Send + 'static,
+    ) -> Arc<Mutex<Vec<String>>> {
+        let _ = std::fs::remove_file(path);
+        let listener = UnixListener::bind(path).unwrap();
+
+        let requests = Arc::new(Mutex::new(vec![]));
+        let requests_clone = requests.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let request = read(&mut stream);
+                let reply = reply(&request);
+                // recorded before replying, so it's there once the client has its reply
+                requests_clone.lock().unwrap().push(request);
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+        });
+        requests
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn visible_windows_are_on_the_shown_workspace() {
+        let rect = Rect::default();
+        let mut hidden = mock::window(2, rect);
+        hidden.workspace = "2".to_string();
+        let mut other_output = mock::window(3, rect);
+        other_output.output.name = "DP-2".to_string();
+
+        let mut layout = mock::layout(vec![mock::window(1, rect), hidden, other_output]);
+        layout.workspaces.push(Workspace {
+            id: 2,
+            name: "2".to_string(),
+            output: "DP-1".to_string(),
+            visible: false,
+            focused: false,
+        });
+
+        let visible: Vec<i64> = layout
+            .visible_windows(&mock::output())
+            .iter()
+            .map(|window| window.id)
+            .collect();
+        assert_eq!(visible, [1]);
+    }
+}
@@ -0,0 +1,165 @@
+use std::cell::Cell;
+use std::time::Duration;
+
+use gtk4::glib::{self, translate::ToGlibPtr, ControlFlow};
+use gtk4::{gdk, prelude::*};
+use x11::xlib;
+
+use crate::wm;
+
+// gdk4-x11 isn't packaged everywhere gtk4 is, and these two are all that's needed from it
+extern "C" {
+    fn gdk_x11_display_get_xdisplay(display: *mut gdk::ffi::GdkDisplay) -> *mut xlib::Display;
+    fn gdk_x11_surface_get_xid(surface: *mut gdk::ffi::GdkSurface) -> xlib::Window;
+}
+
+/// How often to try taking the keyboard while the overlay is being mapped.
+const GRAB_ATTEMPTS: u32 = 20;
+
+pub fn is_x11() -> bool {
+    gdk::Display::default().is_some_and(|display| display.type_().name() == "GdkX11Display")
+}
+
+/// The X display and window behind `window`, once it is realized.
+fn x_window(window: &gtk4::Window) -> Option<(*mut xlib::Display, xlib::Window)> {
+    let surface = window.surface()?;
+    let display = surface.display();
+    // SAFETY: both are X11 objects, see `is_x11`
+    unsafe {
+        Some((
+            gdk_x11_display_get_xdisplay(display.to_glib_none().0),
+            gdk_x11_surface_get_xid(surface.to_glib_none().0),
+        ))
+    }
+}
+
+/// Makes `window` an override-redirect window over `rect`, which the window manager leaves out
+/// of its tree, and grabs the keyboard for it once it is shown, leaving the focus where it was.
+/// Call before the window is presented.
+pub fn override_redirect(window: &gtk4::Window, rect: wm::Rect) {
+    window.set_decorated(false);
+    window.set_default_size(rect.width, rect.height);
+
+    window.connect_realize(|window| {
+        let Some((display, xid)) = x_window(window) else {
+            return;
+        };
+        // SAFETY: the window exists and hasn't been mapped yet
+        unsafe {
+            let mut attributes: xlib::XSetWindowAttributes = std::mem::zeroed();
+            attributes.override_redirect = xlib::True;
+            xlib::XChangeWindowAttributes(display, xid, xlib::CWOverrideRedirect, &mut attributes);
+        }
+    });
+
+    window.connect_map(move |window| {
+        let Some((display, xid)) = x_window(window) else {
+            return;
+        };
+        // SAFETY: the window is mapped and is only used while GTK keeps it
+        unsafe {
+            xlib::XMoveResizeWindow(
+                display,
+                xid,
+                rect.x,
+                rect.y,
+                rect.width as u32,
+                rect.height as u32,
+            );
+            xlib::XRaiseWindow(display, xid);
+            xlib::XFlush(display);
+        }
+
+        // the server may not have mapped the window yet, in which case the grab fails
+        let attempts = Cell::new(0);
+        let window = window.clone();
+        glib::timeout_add_local(Duration::from_millis(25), move || {
+            let Some((display, xid)) = x_window(&window) else {
+                return ControlFlow::Break;
+            };
+            // SAFETY: as above
+            let grabbed = unsafe {
+                let status = xlib::XGrabKeyboard(
+                    display,
+                    xid,
+                    xlib::False,
+                    xlib::GrabModeAsync,
+                    xlib::GrabModeAsync,
+                    xlib::CurrentTime,
+                );
+                status == xlib::GrabSuccess
+            };
+            attempts.set(attempts.get() + 1);
+            if grabbed || attempts.get() == GRAB_ATTEMPTS {
+                if !grabbed {
+                    eprintln!("failed to grab the keyboard");
+                }
+                return ControlFlow::Break;
+            }
+            ControlFlow::Continue
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    // run with `xvfb-run cargo test -- --ignored`
+    #[test]
+    #[ignore = "needs an X server"]
+    fn overlays_are_override_redirect() {
+        std::env::set_var("GDK_BACKEND", "x11");
+        gtk4::init().expect("failed to open the X display");
+        assert!(is_x11());
+
+        let window = gtk4::Window::new();
+        let rect = wm::Rect {
+            x: 100,
+            y: 50,
+            width: 300,
+            height: 200,
+        };
+        override_redirect(&window, rect);
+        window.present();
+
+        // give the window time to be mapped and the keyboard to be grabbed
+        let context = glib::MainContext::default();
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(500) {
+            context.iteration(false);
+        }
+
+        let (display, xid) = x_window(&window).unwrap();
+        // SAFETY: the window is still shown, and the second connection is only used here
+        unsafe {
+            let mut attributes: xlib::XWindowAttributes = std::mem::zeroed();
+            xlib::XGetWindowAttributes(display, xid, &mut attributes);
+            assert_eq!(attributes.override_redirect, xlib::True);
+            assert_eq!(attributes.map_state, xlib::IsViewable);
+            assert_eq!(
+                (
+                    attributes.x,
+                    attributes.y,
+                    attributes.width,
+                    attributes.height
+                ),
+                (rect.x, rect.y, rect.width, rect.height)
+            );
+
+            // nobody else can take the keyboard while the overlay has it
+            let other = xlib::XOpenDisplay(std::ptr::null());
+            let status = xlib::XGrabKeyboard(
+                other,
+                xlib::XDefaultRootWindow(other),
+                xlib::False,
+                xlib::GrabModeAsync,
+                xlib::GrabModeAsync,
+                xlib::CurrentTime,
+            );
+            xlib::XCloseDisplay(other);
+            assert_eq!(status, xlib::AlreadyGrabbed);
+        }
+    }
+}